@@ -0,0 +1,53 @@
+//! Times every available solver on the puzzles of `lights_out bench`, with the statistics of
+//! criterion. Puzzles a solver does not support are skipped.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use lights_out::benchmark::{self, Case, Draw};
+use lights_out::data::{SolverState, SolvingError};
+use lights_out::settings::{Optimize, Settings};
+use lights_out::solver::Control;
+
+/// Seed of every puzzle, so that runs compare the same puzzles
+const SEED: u32 = 0;
+
+fn solvers(c: &mut Criterion) {
+    let settings = Settings {
+        optimize: Optimize::Clicks,
+        ..Settings::default()
+    };
+    for states in [2, 3] {
+        let mut group = c.benchmark_group(format!("{} states", states));
+        group.sample_size(10);
+        for (size, draw) in [3, 4, 5, 6]
+            .into_iter()
+            .flat_map(|size| [Draw::Clicks, Draw::States].map(|draw| (size, draw)))
+        {
+            let case = Case {
+                rows: size,
+                columns: size,
+                states,
+                draw,
+            };
+            let puzzle = case.puzzle(&settings, SEED);
+            for solver in benchmark::available_solvers(&settings) {
+                let engine = SolverState::solver(&Settings {
+                    solver: solver.clone(),
+                    ..settings.clone()
+                });
+                if let Err(SolvingError::Unsupported(_)) = engine.solve(&puzzle, &Control::new()) {
+                    continue;
+                }
+                let id = BenchmarkId::new(
+                    solver.to_string(),
+                    format!("{}x{} random {}", size, size, draw.name()),
+                );
+                group.bench_with_input(id, &puzzle, |b, puzzle| {
+                    b.iter(|| engine.solve(puzzle, &Control::new()))
+                });
+            }
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, solvers);
+criterion_main!(benches);
@@ -0,0 +1,328 @@
+//! Benchmarks of the solvers on random puzzles of several sizes and numbers of states.
+//!
+//! Every solver runs on the same puzzle of each case, generated from a seed, and the answers are
+//! cross-checked: each solution must reach the target, and the solvers that succeed must agree on
+//! whether the puzzle is solvable and, when optimizing, on the cost of the optimal solution.
+use crate::data::{GridCoord, Puzzle, SolverState, SolvingError};
+use crate::settings::{Optimize, Settings, Solver};
+use crate::solver::{Clingo, Control};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::fmt::{self, Display, Write};
+use std::time::{Duration, Instant};
+
+/// A puzzle layout to benchmark, the other parameters coming from the settings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Case {
+    pub rows: usize,
+    pub columns: usize,
+    pub states: usize,
+    pub draw: Draw,
+}
+
+/// How the random puzzle of a case is drawn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Draw {
+    /// Random clicks from the target, which make the puzzle solvable
+    Clicks,
+    /// Random states of the cells, which often make the puzzle not solvable
+    States,
+}
+
+impl Draw {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Draw::Clicks => "clicks",
+            Draw::States => "states",
+        }
+    }
+}
+
+impl Case {
+    /// The random puzzle of this case generated from `seed`, laid out according to `settings`.
+    pub fn puzzle(&self, settings: &Settings, seed: u32) -> Puzzle {
+        let settings = Settings {
+            rows: self.rows,
+            columns: self.columns,
+            states: self.states,
+            objective: settings.objective % self.states,
+            ..settings.clone()
+        };
+        let mut solver_state = SolverState::from_settings(&settings);
+        match self.draw {
+            Draw::Clicks => solver_state.randomize_with(seed),
+            Draw::States => {
+                let mut rng = ChaCha8Rng::seed_from_u64(seed.into());
+                let puzzle = &mut solver_state.params.puzzle;
+                let states: Vec<usize> = puzzle
+                    .storage
+                    .iter()
+                    .map(|cell| rng.gen_range(0..cell.modulus))
+                    .collect();
+                puzzle.set_cell_states(&states);
+            }
+        }
+        solver_state.params.to_puzzle()
+    }
+}
+
+impl Display for Case {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{}, {} states, random {}",
+            self.rows,
+            self.columns,
+            self.states,
+            self.draw.name()
+        )
+    }
+}
+
+/// The solvers that can run on this machine, the `clingo` one only if its executable is found.
+pub fn available_solvers(settings: &Settings) -> Vec<Solver> {
+    let threads = match settings.solver {
+        Solver::InternalPar { threads } => threads,
+        _ => 0,
+    };
+    let clingo_path = match &settings.solver {
+        Solver::Clingo { clingo_path } => clingo_path.clone(),
+        _ => String::from("clingo"),
+    };
+    let clingo = Clingo {
+        clingo_path: clingo_path.clone(),
+        optimize: settings.optimize,
+    };
+    let mut solvers = vec![
+        Solver::Internal,
+        Solver::InternalPar { threads },
+        Solver::Linear,
+        Solver::Bitset,
+    ];
+    if clingo.is_available() {
+        solvers.push(Solver::Clingo { clingo_path });
+    }
+    solvers
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Solved with a solution of the given cost, the total clicks when not optimizing
+    Solved(usize),
+    NotSolvable,
+    /// The solution returned does not bring the puzzle to the target
+    Wrong,
+    Unsupported,
+    /// Stopped by the time limit or the iteration limit
+    OutOfBudget,
+    Failed(String),
+}
+
+impl Outcome {
+    /// Whether the solver answered, rightly or wrongly.
+    fn is_answer(&self) -> bool {
+        matches!(
+            self,
+            Outcome::Solved(_) | Outcome::NotSolvable | Outcome::Wrong
+        )
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Solved(cost) => write!(f, "solved ({})", cost),
+            Outcome::NotSolvable => write!(f, "not solvable"),
+            Outcome::Wrong => write!(f, "wrong solution"),
+            Outcome::Unsupported => write!(f, "unsupported"),
+            Outcome::OutOfBudget => write!(f, "out of time or iterations"),
+            Outcome::Failed(error) => write!(f, "failed: {}", error),
+        }
+    }
+}
+
+/// A solver run on a case.
+#[derive(Clone, Debug)]
+pub struct Measurement {
+    pub case: Case,
+    pub solver: String,
+    /// Fastest of the runs, if none failed
+    pub time: Option<Duration>,
+    pub outcome: Outcome,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub measurements: Vec<Measurement>,
+}
+
+/// Runs every solver in `solvers` `runs` times on the puzzle of each case, with the optimization
+/// and the limits of `settings`. `progress` is called after each measurement.
+pub fn run(
+    settings: &Settings,
+    cases: &[Case],
+    solvers: &[Solver],
+    runs: usize,
+    seed: u32,
+    mut progress: impl FnMut(&Measurement),
+) -> Report {
+    let mut report = Report::default();
+    for &case in cases {
+        let puzzle = case.puzzle(settings, seed);
+        for solver in solvers {
+            let measurement = measure(&puzzle, settings, solver, runs, case);
+            progress(&measurement);
+            report.measurements.push(measurement);
+        }
+    }
+    report
+}
+
+fn measure(
+    puzzle: &Puzzle,
+    settings: &Settings,
+    solver: &Solver,
+    runs: usize,
+    case: Case,
+) -> Measurement {
+    let engine = SolverState::solver(&Settings {
+        solver: solver.clone(),
+        ..settings.clone()
+    });
+    let mut time: Option<Duration> = None;
+    let mut outcome = Outcome::NotSolvable;
+    for _ in 0..runs.max(1) {
+        let start = Instant::now();
+        let result = engine.solve(puzzle, &Control::with_limits(settings.limits));
+        let elapsed = start.elapsed();
+        outcome = match result {
+            Ok(solution) if !solution.solvable => Outcome::NotSolvable,
+            Ok(solution) if !reaches_target(puzzle, &solution.clicks) => Outcome::Wrong,
+            Ok(solution) => Outcome::Solved(match engine.optimized() {
+                Optimize::None => solution.total_clicks,
+                optimize => optimize.cost(&solution.clicks),
+            }),
+            Err(SolvingError::Unsupported(_)) => Outcome::Unsupported,
+            Err(SolvingError::Timeout(_)) => Outcome::OutOfBudget,
+            Err(e) => Outcome::Failed(e.to_string()),
+        };
+        if !outcome.is_answer() {
+            time = None;
+            break;
+        }
+        time = Some(time.map_or(elapsed, |time| time.min(elapsed)));
+    }
+    Measurement {
+        case,
+        solver: solver.to_string(),
+        time,
+        outcome,
+    }
+}
+
+/// Whether performing `clicks` on `puzzle` brings every enabled cell to its target.
+fn reaches_target(puzzle: &Puzzle, clicks: &[usize]) -> bool {
+    let mut grid = puzzle.grid.clone();
+    for row in 0..grid.rows {
+        for col in 0..grid.columns {
+            let coord = GridCoord { row, col };
+            let n = clicks[row * grid.columns + col];
+            if n > 0 && !grid[coord].disabled {
+                grid.click_adjacent_unchecked(coord, n);
+            }
+        }
+    }
+    grid.storage
+        .iter()
+        .zip(puzzle.target.storage.iter())
+        .all(|(cell, target)| cell.disabled || cell.state == target.state)
+}
+
+impl Report {
+    /// The cases on which a solver returned a wrong solution or the solvers disagree: on whether
+    /// the puzzle is solvable or, if `optimize` is not `Optimize::None`, on the optimal cost.
+    pub fn disagreements(&self, optimize: Optimize) -> Vec<Case> {
+        let mut cases: Vec<Case> = vec![];
+        for case in self.measurements.iter().map(|m| m.case) {
+            if !cases.contains(&case) {
+                cases.push(case);
+            }
+        }
+        cases.retain(|&case| {
+            let answers: Vec<&Outcome> = self
+                .measurements
+                .iter()
+                .filter(|m| m.case == case && m.outcome.is_answer())
+                .map(|m| &m.outcome)
+                .collect();
+            let costs: Vec<usize> = answers
+                .iter()
+                .filter_map(|outcome| match outcome {
+                    Outcome::Solved(cost) => Some(*cost),
+                    _ => None,
+                })
+                .collect();
+            let agree = !answers.contains(&&Outcome::Wrong)
+                && (costs.is_empty() || costs.len() == answers.len())
+                && (optimize == Optimize::None || costs.windows(2).all(|pair| pair[0] == pair[1]));
+            !agree
+        });
+        cases
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("rows,columns,states,puzzle,solver,time_ms,outcome,cost\n");
+        for m in &self.measurements {
+            let (outcome, cost) = match &m.outcome {
+                Outcome::Solved(cost) => ("solved", cost.to_string()),
+                Outcome::NotSolvable => ("not_solvable", String::new()),
+                Outcome::Wrong => ("wrong", String::new()),
+                Outcome::Unsupported => ("unsupported", String::new()),
+                Outcome::OutOfBudget => ("out_of_budget", String::new()),
+                Outcome::Failed(_) => ("failed", String::new()),
+            };
+            writeln!(
+                csv,
+                "{},{},{},{},{},{},{},{}",
+                m.case.rows,
+                m.case.columns,
+                m.case.states,
+                m.case.draw.name(),
+                m.solver,
+                m.time.map(milliseconds).unwrap_or_default(),
+                outcome,
+                cost
+            )
+            .expect("writing to a string cannot fail");
+        }
+        csv
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = String::from(
+            "| Rows | Columns | States | Puzzle | Solver | Time (ms) | Outcome |\n\
+             |---:|---:|---:|---|---|---:|---|\n",
+        );
+        for m in &self.measurements {
+            writeln!(
+                markdown,
+                "| {} | {} | {} | random {} | {} | {} | {} |",
+                m.case.rows,
+                m.case.columns,
+                m.case.states,
+                m.case.draw.name(),
+                m.solver,
+                m.time
+                    .map(milliseconds)
+                    .unwrap_or_else(|| String::from("-")),
+                m.outcome
+            )
+            .expect("writing to a string cannot fail");
+        }
+        markdown
+    }
+}
+
+fn milliseconds(time: Duration) -> String {
+    format!("{:.3}", time.as_secs_f64() * 1000.0)
+}
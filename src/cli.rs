@@ -0,0 +1,536 @@
+use config::ConfigError;
+use derive_more::{Display, Error, From};
+use lights_out::benchmark::{self, Case, Draw};
+use lights_out::data::{LoadingError, Params, SolverState, SolvingError};
+use lights_out::file::FileError;
+use lights_out::generator::{Difficulty, GenerationError, Goal};
+use lights_out::neighbourhood::{Neighbourhood, Tiling};
+use lights_out::notation::Notation;
+use lights_out::settings::{Optimize, Settings, Solver};
+use lights_out::solver::Control;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use strum::EnumString;
+
+const USAGE: &str = "\
+Usage: lights_out solve [FILE] [OPTIONS]
+       lights_out generate [OPTIONS]
+       lights_out bench [OPTIONS]
+
+`solve` solves the puzzle read from FILE (or from the standard input if FILE is missing or `-`), or
+the random puzzle generated from the seed given with --seed.
+The puzzle is a grid of cell states separated by whitespace, one row per line, with `-`
+marking disabled cells and `#` starting a comment, or any of the notations listed under
+--format. Puzzle files saved by the GUI (ending in `.toml`) are read with all their
+parameters, ignoring the options that describe the puzzle.
+
+`generate` prints random puzzles of the given difficulty, rated by the cost of their optimal
+solution, in the notation given with --format.
+
+`bench` times the solvers on random puzzles of several sizes and numbers of states, checks that
+their answers agree and prints a report. Solvers unsupported by a puzzle are reported as such.
+
+Options (the defaults are read from settings.toml, if present):
+    --solver <clingo|internal|internal_par|linear|bitset>
+    --threads <N>                 threads of the internal_par solver, 0 for all
+    --clingo-path <PATH>          clingo executable, `clingo` by default
+    --optimize <none|clicks|pressed_cells>
+    --rows <N>                    rows of the random puzzle
+    --columns <N>                 columns of the random puzzle
+    --seed <N>                    solve the random puzzle generated from this seed, from 0 to
+                                  4294967295, printing it first (with `generate`, the seed of
+                                  the first puzzle, each next one using the next seed)
+    --states <N>
+    --objective <N>               target state of every cell
+    --topology <bounded|torus|horizontal_cylinder|vertical_cylinder>
+    --tiling <square|hex|triangle>
+    --neighbourhood <plus|x_shape|moore|knight>
+                                  cells affected by a click, square cells only
+    --radius <N>                  size of the neighbourhood, square cells only
+    --centre-weight <N>
+    --neighbour-weight <N>
+    --graph <FILE>                play on the nodes of a graph, the puzzle listing their states
+    --format <text|ascii|rle|asp> notation of the puzzle, guessed by default
+    --time-limit <SECONDS>        stop the solver after this time, 0 for no limit
+    --max-iterations <N>          stop the solver after this many steps, 0 for no limit
+
+Options of `generate`:
+    --count <N>                   puzzles to generate, 1 by default
+    --difficulty <easy|medium|hard>
+                                  share of the cells the optimal solution takes, from up to 20%
+                                  to at least 40%, `medium` by default
+    --metric <clicks|pressed_cells>
+                                  cost of the optimal solution, --optimize by default
+    --min-cost <N>                lowest cost of the optimal solution, instead of --difficulty
+    --max-cost <N>                highest cost of the optimal solution, instead of --difficulty
+    --unique                      require the optimal solution to be the only one of its cost
+
+Options of `bench` (with --seed giving the seed of every puzzle):
+    --sizes <RxC,...>             sizes of the puzzles, `3x3,4x4,5x5,6x6` by default
+    --states <N,...>              numbers of states of the puzzles, `2,3` by default
+    --solver <S,...>              solvers to compare, all those available by default
+    --runs <N>                    runs of each solver on each puzzle, keeping the fastest, 3 by
+                                  default
+    --report <markdown|csv>       format of the report, `markdown` by default
+
+Exit status: 0 if the puzzle was solved, 2 if it is not solvable, 3 if the solver ran out of time
+or iterations, 1 on other errors, including solvers disagreeing in a benchmark.";
+
+#[derive(From, Debug, Display, Error)]
+pub enum CliError {
+    Usage(#[error(not(source))] String),
+    Io(std::io::Error),
+    Config(ConfigError),
+    Loading(LoadingError),
+    Puzzle(FileError),
+    Solving(SolvingError),
+    Generation(GenerationError),
+    #[from(ignore)]
+    Disagreement(#[error(not(source))] String),
+}
+
+#[derive(EnumString, Clone, Copy)]
+#[strum(serialize_all = "snake_case")]
+enum ReportFormat {
+    Markdown,
+    Csv,
+}
+
+/// Rows and columns of a puzzle, written as `RxC`.
+struct Size(usize, usize);
+
+impl FromStr for Size {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (rows, columns) = s.split_once('x').ok_or(())?;
+        Ok(Size(
+            rows.parse().map_err(|_| ())?,
+            columns.parse().map_err(|_| ())?,
+        ))
+    }
+}
+
+/// The options of a command, those describing the puzzle and the solver being stored in
+/// `settings`.
+struct Options {
+    settings: Settings,
+    file: Option<String>,
+    graph: Option<String>,
+    format: Option<Notation>,
+    seed: Option<u32>,
+    count: usize,
+    difficulty: Difficulty,
+    metric: Option<Optimize>,
+    min_cost: Option<usize>,
+    max_cost: Option<usize>,
+    unique: bool,
+    sizes: Vec<Size>,
+    state_counts: Vec<usize>,
+    solvers: Option<Vec<Solver>>,
+    runs: usize,
+    report: ReportFormat,
+}
+
+/// Runs the command line interface, returning the exit status of the process.
+pub fn run(args: &[String]) -> i32 {
+    let result = match args.first().map(String::as_str) {
+        Some(command @ ("solve" | "generate" | "bench")) => parse_options(command, &args[1..])
+            .and_then(|options| match command {
+                "solve" => solve(options),
+                "generate" => generate(options).map(|()| true),
+                _ => bench(options).map(|()| true),
+            }),
+        Some("help" | "--help" | "-h") => {
+            println!("{}", USAGE);
+            Ok(true)
+        }
+        _ => Err(CliError::Usage(String::from("Unknown command"))),
+    };
+    match result {
+        Ok(true) => 0,
+        Ok(false) => 2,
+        Err(CliError::Usage(message)) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            1
+        }
+        Err(
+            CliError::Solving(e @ SolvingError::Timeout(_))
+            | CliError::Generation(GenerationError::Solving(e @ SolvingError::Timeout(_))),
+        ) => {
+            eprintln!("Error: {}", e);
+            3
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            1
+        }
+    }
+}
+
+fn value<T: FromStr>(option: &str, value: Option<&String>) -> Result<T, CliError> {
+    value
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| CliError::Usage(format!("Invalid or missing value for {}", option)))
+}
+
+/// Reads the comma separated values of `option`.
+fn list<T: FromStr>(option: &str, value: Option<&String>) -> Result<Vec<T>, CliError> {
+    value
+        .and_then(|value| {
+            value
+                .split(',')
+                .map(|item| item.trim().parse().ok())
+                .collect::<Option<Vec<T>>>()
+        })
+        .ok_or_else(|| CliError::Usage(format!("Invalid or missing value for {}", option)))
+}
+
+/// Reads the options of `command` from `args`, on top of the settings.
+fn parse_options(command: &str, args: &[String]) -> Result<Options, CliError> {
+    let generating = command == "generate";
+    let benching = command == "bench";
+    let mut settings = if Path::new("settings.toml").exists() {
+        Settings::new()?
+    } else {
+        Settings::default()
+    };
+    let (mut threads, mut clingo_path) = match settings.solver {
+        Solver::InternalPar { threads } => (threads, String::from("clingo")),
+        Solver::Clingo { ref clingo_path } => (0, clingo_path.clone()),
+        _ => (0, String::from("clingo")),
+    };
+    let mut file = None;
+    let mut graph = None;
+    let mut format = None;
+    let mut seed = None;
+    let mut count = 1;
+    let mut difficulty = Difficulty::Medium;
+    let mut metric = None;
+    let mut min_cost = None;
+    let mut max_cost = None;
+    let mut unique = false;
+    let mut sizes = vec![Size(3, 3), Size(4, 4), Size(5, 5), Size(6, 6)];
+    let mut state_counts = vec![2, 3];
+    let mut solvers = None;
+    let mut runs = 3;
+    let mut report = ReportFormat::Markdown;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--solver" if benching => solvers = Some(list("--solver", args.next())?),
+            "--states" if benching => state_counts = list("--states", args.next())?,
+            "--sizes" if benching => sizes = list("--sizes", args.next())?,
+            "--runs" if benching => runs = value("--runs", args.next())?,
+            "--report" if benching => report = value("--report", args.next())?,
+            "--solver" => settings.solver = value("--solver", args.next())?,
+            "--threads" => threads = value("--threads", args.next())?,
+            "--clingo-path" => clingo_path = value("--clingo-path", args.next())?,
+            "--optimize" => settings.optimize = value("--optimize", args.next())?,
+            "--rows" => settings.rows = value("--rows", args.next())?,
+            "--columns" => settings.columns = value("--columns", args.next())?,
+            "--seed" => seed = Some(value("--seed", args.next())?),
+            "--states" => settings.states = value("--states", args.next())?,
+            "--objective" => settings.objective = value("--objective", args.next())?,
+            "--topology" => settings.topology = value("--topology", args.next())?,
+            "--tiling" => settings.tiling = value("--tiling", args.next())?,
+            "--neighbourhood" => settings.neighbourhood = value("--neighbourhood", args.next())?,
+            "--radius" => settings.radius = value("--radius", args.next())?,
+            "--centre-weight" => settings.centre_weight = value("--centre-weight", args.next())?,
+            "--neighbour-weight" => {
+                settings.neighbour_weight = value("--neighbour-weight", args.next())?
+            }
+            "--graph" => graph = Some(value::<String>("--graph", args.next())?),
+            "--format" => format = Some(value("--format", args.next())?),
+            "--time-limit" => {
+                let time = Duration::try_from_secs_f64(value("--time-limit", args.next())?)
+                    .map_err(|_| CliError::Usage(String::from("Invalid value for --time-limit")))?;
+                settings.limits.time = Some(time).filter(|time| !time.is_zero());
+            }
+            "--max-iterations" => {
+                settings.limits.iterations = match value("--max-iterations", args.next())? {
+                    0 => None,
+                    iterations => Some(iterations),
+                }
+            }
+            "--count" if generating => count = value("--count", args.next())?,
+            "--difficulty" if generating => difficulty = value("--difficulty", args.next())?,
+            "--metric" if generating => metric = Some(value("--metric", args.next())?),
+            "--min-cost" if generating => min_cost = Some(value("--min-cost", args.next())?),
+            "--max-cost" if generating => max_cost = Some(value("--max-cost", args.next())?),
+            "--unique" if generating => unique = true,
+            option if option.starts_with("--") => {
+                return Err(CliError::Usage(format!(
+                    "Unknown option {} for {}",
+                    option, command
+                )))
+            }
+            _ if generating || benching => {
+                return Err(CliError::Usage(String::from(
+                    "Puzzles are generated without reading any file",
+                )))
+            }
+            path if file.is_none() => file = Some(String::from(path)),
+            _ => return Err(CliError::Usage(String::from("Too many files"))),
+        }
+    }
+
+    if state_counts.iter().any(|&states| states < 2) {
+        return Err(CliError::Usage(String::from(
+            "There must be at least 2 states",
+        )));
+    }
+    if sizes.iter().any(|size| size.0 == 0 || size.1 == 0) {
+        return Err(CliError::Usage(String::from(
+            "The puzzles must have at least one cell",
+        )));
+    }
+    if settings.states < 2 {
+        return Err(CliError::Usage(String::from(
+            "There must be at least 2 states",
+        )));
+    }
+    if settings.rows == 0 || settings.columns == 0 {
+        return Err(CliError::Usage(String::from(
+            "The puzzle must have at least one cell",
+        )));
+    }
+    if settings.radius == 0 {
+        return Err(CliError::Usage(String::from("The radius must be positive")));
+    }
+    if settings.neighbourhood == Neighbourhood::Custom {
+        return Err(CliError::Usage(String::from(
+            "Custom neighbourhoods can only be drawn in the GUI",
+        )));
+    }
+    // Hexagonal and triangular cells always affect the cells sharing an edge with them
+    if settings.tiling != Tiling::Square
+        && (settings.neighbourhood != Neighbourhood::Plus || settings.radius != 1)
+    {
+        return Err(CliError::Usage(String::from(
+            "The neighbourhood and radius only apply to square cells, hexagonal and triangular \
+             ones need the plus of radius 1",
+        )));
+    }
+    settings.objective %= settings.states;
+    let configure = |solver| match solver {
+        Solver::Clingo { .. } => Solver::Clingo {
+            clingo_path: clingo_path.clone(),
+        },
+        Solver::InternalPar { .. } => Solver::InternalPar { threads },
+        solver => solver,
+    };
+    let solvers = solvers.map(|solvers| solvers.into_iter().map(&configure).collect());
+    settings.solver = configure(settings.solver);
+
+    Ok(Options {
+        settings,
+        file,
+        graph,
+        format,
+        seed,
+        count,
+        difficulty,
+        metric,
+        min_cost,
+        max_cost,
+        unique,
+        sizes,
+        state_counts,
+        solvers,
+        runs,
+        report,
+    })
+}
+
+/// Solves the puzzle described by `options`, returning whether it is solvable.
+fn solve(options: Options) -> Result<bool, CliError> {
+    let Options {
+        settings,
+        file,
+        graph,
+        format,
+        seed,
+        ..
+    } = options;
+    let mut solver_state = match seed {
+        Some(_) if file.is_some() => {
+            return Err(CliError::Usage(String::from(
+                "A puzzle cannot be both read and generated with --seed",
+            )))
+        }
+        Some(seed) => {
+            let solver_state = random_puzzle(seed, graph, &settings)?;
+            println!("Puzzle (seed {}):", seed);
+            print!("{}", solver_state.params.puzzle.to_text());
+            solver_state
+        }
+        None => read_puzzle(file.as_deref(), graph, format, &settings)?,
+    };
+
+    let solver = SolverState::solver(&settings);
+    let solution = solver_state.solve(&*solver, &Control::with_limits(settings.limits))?;
+
+    if solution.solvable {
+        println!("Solution:");
+        print!("{}", solver_state.params.solution.to_text());
+        println!("Total clicks: {}", solution.total_clicks);
+    }
+    match solver.optimized().description() {
+        Some(metric) => println!("Time: {:?} ({})", solution.time, metric),
+        None => println!("Time: {:?}", solution.time),
+    }
+    if solution.stats.candidates > 0 {
+        println!(
+            "Candidates checked: {}, solutions found: {}",
+            solution.stats.candidates, solution.stats.solutions
+        );
+    }
+    println!("Solvable: {}", if solution.solvable { "yes" } else { "no" });
+
+    Ok(solution.solvable)
+}
+
+/// Prints `options.count` puzzles generated to the difficulty described by `options`.
+fn generate(options: Options) -> Result<(), CliError> {
+    let settings = &options.settings;
+    let metric = match options.metric.unwrap_or(settings.optimize) {
+        Optimize::None => Optimize::Clicks,
+        metric => metric,
+    };
+    let mut solver_state = SolverState::from_settings(settings);
+    if let Some(path) = options.graph {
+        solver_state.params.load_graph(&path)?;
+    }
+    let preset = options
+        .difficulty
+        .goal(&solver_state.params, metric, options.unique);
+    let goal = Goal {
+        min: options.min_cost.unwrap_or(preset.min),
+        max: options.max_cost.unwrap_or(preset.max),
+        ..preset
+    };
+    let format = options.format.unwrap_or(Notation::Text);
+    let comment = if format == Notation::Asp { "%" } else { "#" };
+    let unit = match metric {
+        Optimize::PressedCells => "pressed cells",
+        _ => "clicks",
+    };
+
+    let first_seed = options.seed.unwrap_or_else(rand::random);
+    for i in 0..options.count {
+        let seed = first_seed.wrapping_add(i as u32);
+        let control = Control::with_limits(settings.limits);
+        let cost = solver_state.generate(&goal, seed, &control)?;
+        if i > 0 {
+            println!();
+        }
+        println!(
+            "{} Puzzle {} (seed {}): optimal solution of {} {}",
+            comment,
+            i + 1,
+            seed,
+            cost,
+            unit
+        );
+        println!("{}", format.write(&solver_state.params.puzzle)?.trim_end());
+    }
+    Ok(())
+}
+
+/// Times the solvers on the puzzles described by `options`, printing the report and failing if
+/// their answers disagree.
+fn bench(options: Options) -> Result<(), CliError> {
+    let settings = &options.settings;
+    let cases: Vec<Case> = options
+        .state_counts
+        .iter()
+        .flat_map(|&states| {
+            options.sizes.iter().flat_map(move |size| {
+                [Draw::Clicks, Draw::States].map(|draw| Case {
+                    rows: size.0,
+                    columns: size.1,
+                    states,
+                    draw,
+                })
+            })
+        })
+        .collect();
+    let solvers = options
+        .solvers
+        .unwrap_or_else(|| benchmark::available_solvers(settings));
+    let seed = options.seed.unwrap_or_else(rand::random);
+
+    eprintln!("Puzzles of seed {}", seed);
+    let report = benchmark::run(settings, &cases, &solvers, options.runs, seed, |m| {
+        eprintln!("{}: {} {}", m.case, m.solver, m.outcome)
+    });
+    match options.report {
+        ReportFormat::Markdown => print!("{}", report.to_markdown()),
+        ReportFormat::Csv => print!("{}", report.to_csv()),
+    }
+
+    let disagreements = report.disagreements(settings.optimize);
+    if disagreements.is_empty() {
+        Ok(())
+    } else {
+        let cases: Vec<String> = disagreements.iter().map(Case::to_string).collect();
+        Err(CliError::Disagreement(format!(
+            "the solvers disagree on {}",
+            cases.join("; ")
+        )))
+    }
+}
+
+/// Reads the puzzle from a puzzle file, or from the states of its cells (read from the standard
+/// input if `file` is missing or `-`) written in `format` and laid out according to `settings` or
+/// `graph`.
+fn read_puzzle(
+    file: Option<&str>,
+    graph: Option<String>,
+    format: Option<Notation>,
+    settings: &Settings,
+) -> Result<SolverState, CliError> {
+    if let Some(path) = file.filter(|path| path.ends_with(".toml")) {
+        let mut solver_state = SolverState::from_settings(settings);
+        solver_state.params = Params::open(path)?;
+        return Ok(solver_state);
+    }
+
+    let mut text = String::new();
+    match file {
+        Some(path) if path != "-" => text = std::fs::read_to_string(path)?,
+        _ => {
+            std::io::stdin().read_to_string(&mut text)?;
+        }
+    }
+    let cells = format
+        .unwrap_or_else(|| Notation::detect(&text))
+        .read(&text)?;
+
+    let mut solver_state = SolverState::from_settings(settings);
+    let params = &mut solver_state.params;
+    if let Some(path) = graph {
+        params.load_graph(&path)?;
+    }
+    params.set_puzzle(&cells)?;
+
+    Ok(solver_state)
+}
+
+/// Generates a solvable puzzle from `seed`, laid out according to `settings` or `graph`.
+fn random_puzzle(
+    seed: u32,
+    graph: Option<String>,
+    settings: &Settings,
+) -> Result<SolverState, CliError> {
+    let mut solver_state = SolverState::from_settings(settings);
+    if let Some(path) = graph {
+        solver_state.params.load_graph(&path)?;
+    }
+    solver_state.randomize_with(seed);
+    Ok(solver_state)
+}
@@ -1,77 +1,466 @@
-use crate::data::Params;
-use druid::{widget::Controller, Event, Selector, Widget};
+use crate::state::{AppState, ParamsState};
+use druid::{
+    commands, widget::Controller, Application, Event, Selector, SingleUse, Target, TimerToken,
+    Widget,
+};
+use lights_out::data::{Grid, Params, Solution, SolutionSet, SolverState, SolvingError};
+use lights_out::generator::{GenerationError, Goal};
+use lights_out::notation::Notation;
+use lights_out::settings::Optimize;
+use lights_out::solver::{Control, Solvability};
+use std::thread;
+use std::time::{Duration, Instant};
 
-const UPDATED: Selector = Selector::new("lights_out.update_textbox");
-const PLAY_CHANGED: Selector = Selector::new("lights_out.play_changed");
+pub const SOLVE: Selector = Selector::new("lights_out.solve");
+pub const SOLVE_ALL: Selector = Selector::new("lights_out.solve_all");
+pub const GENERATE: Selector<Goal> = Selector::new("lights_out.generate");
+pub const CANCEL: Selector = Selector::new("lights_out.cancel");
+pub const UNDO: Selector = Selector::new("lights_out.undo");
+pub const REDO: Selector = Selector::new("lights_out.redo");
+pub const COPY_AS: Selector<Notation> = Selector::new("lights_out.copy_as");
+pub const PASTE_PUZZLE: Selector = Selector::new("lights_out.paste_puzzle");
+const SOLVED: Selector<Outcome<Solution, Optimize>> = Selector::new("lights_out.solved");
+const SOLVED_ALL: Selector<Outcome<Option<SolutionSet>, Duration>> =
+    Selector::new("lights_out.solved_all");
+const GENERATED: Selector<SingleUse<(Result<usize, GenerationError>, SolverState)>> =
+    Selector::new("lights_out.generated");
+const CHECKS_FOUND: Selector<SingleUse<Solvability>> = Selector::new("lights_out.checks_found");
 
+/// Result of a solver run in the background, sent back along with what the window shows of it
+type Outcome<T, U> = SingleUse<(Result<T, SolvingError>, U)>;
+
+/// How often the progress bar follows the running solver
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Rebuilds the grids when a parameter is edited through the wrapped widget. Parameters changed
+/// in other ways, e.g. by opening a puzzle file, keep their grids.
 pub struct ParamsController;
 
-impl<W: Widget<Params>> Controller<Params, W> for ParamsController {
-    fn update(
+impl<W: Widget<ParamsState>> Controller<ParamsState, W> for ParamsController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut druid::EventCtx,
+        event: &druid::Event,
+        data: &mut ParamsState,
+        env: &druid::Env,
+    ) {
+        let old_params = data.params().clone();
+        child.event(ctx, event, data, env);
+        let params = data.params_mut();
+        params.objective %= params.states;
+        if !params.same_layout(&old_params) {
+            params.reset_grids();
+            data.solve_time.clear();
+        } else if params.objective != old_params.objective {
+            params.reset_target();
+        }
+    }
+}
+
+pub struct StencilController;
+
+impl<W: Widget<ParamsState>> Controller<ParamsState, W> for StencilController {
+    fn event(
         &mut self,
         child: &mut W,
-        ctx: &mut druid::UpdateCtx,
-        old_data: &Params,
-        data: &Params,
+        ctx: &mut druid::EventCtx,
+        event: &druid::Event,
+        data: &mut ParamsState,
         env: &druid::Env,
     ) {
-        if data != old_data {
-            ctx.submit_command(UPDATED);
+        child.event(ctx, event, data, env);
+        let data = data.params_mut();
+        let stencil = data.stencil();
+        if *data.puzzle.stencil() != stencil {
+            data.puzzle.set_stencil(stencil.clone());
+            data.solution.set_stencil(stencil);
         }
-        child.update(ctx, old_data, data, env);
     }
+}
+
+pub struct LayoutController;
 
+impl<W: Widget<ParamsState>> Controller<ParamsState, W> for LayoutController {
     fn event(
         &mut self,
         child: &mut W,
         ctx: &mut druid::EventCtx,
         event: &druid::Event,
-        data: &mut Params,
+        data: &mut ParamsState,
+        env: &druid::Env,
+    ) {
+        child.event(ctx, event, data, env);
+        let data = data.params_mut();
+        // Holes and cell states can be edited both in the puzzle and in the target grid, the
+        // solution grid always holding the previous layout
+        let previous = data.solution.layout();
+        let layout = match data.target.layout() {
+            target if target != previous => target,
+            _ => data.puzzle.layout(),
+        };
+        if layout != previous {
+            data.puzzle.set_layout(&layout);
+            data.target.set_layout(&layout);
+            data.solution.set_layout(&layout);
+            data.solution.fill(0);
+            data.solutions = None;
+        }
+    }
+}
+
+/// Runs the solver on a background thread when asked to by `SOLVE` or `SOLVE_ALL`, or the
+/// generator when asked to by `GENERATE`, showing its progress until it finishes or `CANCEL` stops
+/// it.
+pub struct SolveController {
+    control: Option<Control>,
+    timer: TimerToken,
+}
+
+impl SolveController {
+    pub fn new() -> Self {
+        SolveController {
+            control: None,
+            timer: TimerToken::INVALID,
+        }
+    }
+
+    /// The control of a new background task, if none is running yet.
+    fn begin(&mut self, ctx: &mut druid::EventCtx, data: &mut AppState) -> Option<Control> {
+        if data.busy {
+            return None;
+        }
+        let control = Control::with_limits(crate::SETTINGS.limits);
+        self.control = Some(control.clone());
+        self.timer = ctx.request_timer(PROGRESS_INTERVAL);
+        data.busy = true;
+        data.progress = 0.0;
+        Some(control)
+    }
+
+    fn start(&mut self, ctx: &mut druid::EventCtx, data: &mut AppState, all: bool) {
+        let control = match self.begin(ctx, data) {
+            Some(control) => control,
+            None => return,
+        };
+        data.params.clear_solution();
+
+        let sink = ctx.get_external_handle();
+        let target = Target::Widget(ctx.widget_id());
+        let puzzle = data.params.params().to_puzzle();
+        let solver = SolverState::solver(&crate::SETTINGS);
+        thread::spawn(move || {
+            // The window may have been closed in the meantime, with nobody left to notify
+            let _ = if all {
+                let time = Instant::now();
+                let result = solver.solve_all(&puzzle, &control);
+                sink.submit_command(SOLVED_ALL, SingleUse::new((result, time.elapsed())), target)
+            } else {
+                let result = solver.solve(&puzzle, &control);
+                sink.submit_command(SOLVED, SingleUse::new((result, solver.optimized())), target)
+            };
+        });
+    }
+
+    fn generate(&mut self, ctx: &mut druid::EventCtx, data: &mut AppState, goal: Goal) {
+        let control = match self.begin(ctx, data) {
+            Some(control) => control,
+            None => return,
+        };
+        let sink = ctx.get_external_handle();
+        let target = Target::Widget(ctx.widget_id());
+        let mut state = data.params.state.clone();
+        let seed = rand::random();
+        thread::spawn(move || {
+            let result = state.generate(&goal, seed, &control);
+            let _ = sink.submit_command(GENERATED, SingleUse::new((result, state)), target);
+        });
+    }
+
+    fn finish(&mut self, data: &mut AppState) {
+        self.control = None;
+        self.timer = TimerToken::INVALID;
+        data.busy = false;
+        data.progress = 0.0;
+    }
+}
+
+impl<W: Widget<AppState>> Controller<AppState, W> for SolveController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut druid::EventCtx,
+        event: &druid::Event,
+        data: &mut AppState,
         env: &druid::Env,
     ) {
         match event {
-            Event::Command(cmd) if cmd.is(UPDATED) => {
-                data.solve_time.clear();
-                data.reset_grids()
+            Event::Command(cmd) if cmd.is(SOLVE) => self.start(ctx, data, false),
+            Event::Command(cmd) if cmd.is(SOLVE_ALL) => self.start(ctx, data, true),
+            Event::Command(cmd) if cmd.is(GENERATE) => {
+                self.generate(ctx, data, *cmd.get_unchecked(GENERATE))
             }
-            _ => (),
+            Event::Command(cmd) if cmd.is(CANCEL) => {
+                if let Some(control) = &self.control {
+                    control.cancel();
+                }
+            }
+            Event::Command(cmd) if cmd.is(SOLVED) => {
+                if let Some((result, optimized)) = cmd.get_unchecked(SOLVED).take() {
+                    self.finish(data);
+                    data.params.set_solution(&result, optimized);
+                }
+            }
+            Event::Command(cmd) if cmd.is(SOLVED_ALL) => {
+                if let Some((result, time)) = cmd.get_unchecked(SOLVED_ALL).take() {
+                    self.finish(data);
+                    data.params.set_solutions(result, time);
+                }
+            }
+            Event::Command(cmd) if cmd.is(GENERATED) => {
+                if let Some((result, state)) = cmd.get_unchecked(GENERATED).take() {
+                    self.finish(data);
+                    match result {
+                        Ok(_) => {
+                            data.params.state = state;
+                            data.params.clear_solve_info();
+                        }
+                        Err(e) => {
+                            data.params.solve_error = format!("cannot generate puzzle: {}", e)
+                        }
+                    }
+                }
+            }
+            Event::Timer(token) if *token == self.timer => {
+                if let Some(control) = &self.control {
+                    data.progress = control.progress();
+                    self.timer = ctx.request_timer(PROGRESS_INTERVAL);
+                }
+            }
+            _ => child.event(ctx, event, data, env),
         }
-        data.objective %= data.states;
-        child.event(ctx, event, data, env);
     }
 }
 
-pub struct PlayController;
+/// Keeps the puzzle and its parameters from being edited while a solver is running.
+pub struct BusyController;
 
-impl<W: Widget<Params>> Controller<Params, W> for PlayController {
-    fn update(
+impl<W: Widget<AppState>> Controller<AppState, W> for BusyController {
+    fn event(
         &mut self,
         child: &mut W,
-        ctx: &mut druid::UpdateCtx,
-        old_data: &Params,
-        data: &Params,
+        ctx: &mut druid::EventCtx,
+        event: &druid::Event,
+        data: &mut AppState,
         env: &druid::Env,
     ) {
-        if data.play != old_data.play {
-            ctx.submit_command(PLAY_CHANGED);
+        let input = matches!(
+            event,
+            Event::MouseDown(_)
+                | Event::MouseUp(_)
+                | Event::Wheel(_)
+                | Event::KeyDown(_)
+                | Event::KeyUp(_)
+                | Event::Paste(_)
+        );
+        if !(input && data.busy) {
+            child.event(ctx, event, data, env);
         }
-        child.update(ctx, old_data, data, env);
     }
+}
+
+/// Opens and saves the puzzle files chosen through the File menu.
+pub struct FileController;
 
+impl<W: Widget<AppState>> Controller<AppState, W> for FileController {
     fn event(
         &mut self,
         child: &mut W,
         ctx: &mut druid::EventCtx,
         event: &druid::Event,
-        data: &mut Params,
+        data: &mut AppState,
         env: &druid::Env,
     ) {
         match event {
-            Event::Command(cmd) if cmd.is(PLAY_CHANGED) => {
-                data.puzzle.play = data.play;
+            Event::Command(cmd) if cmd.is(commands::OPEN_FILE) => {
+                ctx.set_handled();
+                if data.busy {
+                    return;
+                }
+                let path = cmd.get_unchecked(commands::OPEN_FILE).path();
+                match Params::open(path) {
+                    Ok(params) => {
+                        data.params = ParamsState {
+                            play: data.params.play,
+                            ..ParamsState::new(SolverState { params })
+                        };
+                    }
+                    Err(e) => data.params.solve_error = format!("cannot open puzzle: {}", e),
+                }
             }
-            _ => (),
+            Event::Command(cmd) if cmd.is(commands::SAVE_FILE_AS) => {
+                ctx.set_handled();
+                let path = cmd.get_unchecked(commands::SAVE_FILE_AS).path();
+                if let Err(e) = data.params.params().save(path) {
+                    data.params.solve_error = format!("cannot save puzzle: {}", e);
+                }
+            }
+            Event::Command(cmd) if cmd.is(COPY_AS) => {
+                ctx.set_handled();
+                match cmd
+                    .get_unchecked(COPY_AS)
+                    .write(&data.params.params().puzzle)
+                {
+                    Ok(text) => Application::global().clipboard().put_string(text),
+                    Err(e) => data.params.solve_error = format!("cannot copy puzzle: {}", e),
+                }
+            }
+            Event::Command(cmd) if cmd.is(PASTE_PUZZLE) => {
+                ctx.set_handled();
+                if data.busy {
+                    return;
+                }
+                let text = Application::global().clipboard().get_string();
+                let text = text.unwrap_or_default();
+                let mut params = data.params.clone();
+                match Notation::detect(&text)
+                    .read(&text)
+                    .and_then(|cells| params.params_mut().set_puzzle(&cells))
+                {
+                    Ok(()) => {
+                        params.solve_time.clear();
+                        data.params = params;
+                    }
+                    Err(e) => data.params.solve_error = format!("cannot paste puzzle: {}", e),
+                }
+            }
+            _ => child.event(ctx, event, data, env),
         }
+    }
+}
+
+/// Randomizes the puzzle again when a new seed is entered in the wrapped widget.
+pub struct SeedController;
+
+impl<W: Widget<AppState>> Controller<AppState, W> for SeedController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut druid::EventCtx,
+        event: &druid::Event,
+        data: &mut AppState,
+        env: &druid::Env,
+    ) {
+        let seed = data.params.params().seed;
         child.event(ctx, event, data, env);
+        match data.params.params().seed {
+            Some(new_seed) if Some(new_seed) != seed => data.params.randomize_with(new_seed),
+            _ => (),
+        }
+    }
+}
+
+/// Records the edits made through the wrapped widget in the history of the puzzle, and goes back
+/// and forth through it.
+pub struct HistoryController;
+
+impl<W: Widget<AppState>> Controller<AppState, W> for HistoryController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut druid::EventCtx,
+        event: &druid::Event,
+        data: &mut AppState,
+        env: &druid::Env,
+    ) {
+        match event {
+            Event::Command(cmd) if cmd.is(UNDO) || cmd.is(REDO) => {
+                ctx.set_handled();
+                // The running solver would show its solution on another puzzle
+                if data.busy {
+                    return;
+                }
+                if cmd.is(UNDO) {
+                    data.undo();
+                } else {
+                    data.redo();
+                }
+            }
+            _ => {
+                let before = data.params.clone();
+                child.event(ctx, event, data, env);
+                data.record(before);
+            }
+        }
+    }
+}
+
+/// Tells whether the puzzle is solvable whenever it changes, finding the checks again on a
+/// background thread only when its layout changes.
+pub struct SolvableController {
+    solvability: Option<Solvability>,
+    /// Whether the checks of a new layout are being found
+    finding: bool,
+    /// Puzzle and target checked last
+    checked: Option<(Grid, Grid)>,
+}
+
+impl SolvableController {
+    pub fn new() -> Self {
+        Self {
+            solvability: None,
+            finding: false,
+            checked: None,
+        }
+    }
+}
+
+impl<W: Widget<AppState>> Controller<AppState, W> for SolvableController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut druid::EventCtx,
+        event: &druid::Event,
+        data: &mut AppState,
+        env: &druid::Env,
+    ) {
+        match event {
+            Event::Command(cmd) if cmd.is(CHECKS_FOUND) => {
+                if let Some(solvability) = cmd.get_unchecked(CHECKS_FOUND).take() {
+                    self.solvability = Some(solvability);
+                    self.finding = false;
+                }
+            }
+            _ => child.event(ctx, event, data, env),
+        }
+        let params = data.params.params();
+        if let Some((puzzle, target)) = &self.checked {
+            if *puzzle == params.puzzle && *target == params.target {
+                return;
+            }
+        }
+        match &self.solvability {
+            Some(solvability) if solvability.applies_to(&params.puzzle) => {
+                let solvable = solvability.is_solvable(&params.puzzle, &params.target);
+                self.checked = Some((params.puzzle.clone(), params.target.clone()));
+                data.params.solvable = Some(solvable);
+            }
+            _ => {
+                // The puzzle is checked again once the checks of its layout are found, or those
+                // of a later layout if it changes in the meantime
+                if !self.finding {
+                    self.finding = true;
+                    let sink = ctx.get_external_handle();
+                    let target = Target::Widget(ctx.widget_id());
+                    let puzzle = params.puzzle.clone();
+                    thread::spawn(move || {
+                        let solvability = Solvability::new(&puzzle);
+                        let _ =
+                            sink.submit_command(CHECKS_FOUND, SingleUse::new(solvability), target);
+                    });
+                }
+                data.params.solvable = None;
+            }
+        }
     }
 }
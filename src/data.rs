@@ -1,29 +1,81 @@
 use core::fmt::Debug;
-use derivative::Derivative;
 use derive_more::{Display, Error, From};
-use druid::{Data, Lens};
 use itertools::Itertools;
-use rand::Rng;
-use std::time::Instant;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
 use std::{io::Error, string::FromUtf8Error};
 use std::{
     ops::{Index, IndexMut},
     sync::Arc,
 };
+use strum::EnumString;
 
-use crate::settings::Solver;
-use crate::solver::{self, Solver as SolverTrait};
+use crate::graph::Graph;
+use crate::neighbourhood::{Neighbourhood, Offset, Stencil, Tiling, Weights};
+use crate::settings::{Optimize, Settings, Solver};
+use crate::solver::{self, Control, Solvability, Solver as SolverTrait};
 
 #[derive(From, Debug, Display, Error)]
 pub enum SolvingError {
     Io(Error),
     FromUtf8(FromUtf8Error),
     Parsing(ParsingError),
+    Unsupported(UnsupportedError),
+    #[display(fmt = "cancelled")]
+    Cancelled,
+    Timeout(TimeoutError),
+}
+
+impl SolvingError {
+    /// Attaches the work done before running out of budget to a timeout.
+    pub(crate) fn with_stats(self, stats: Stats) -> Self {
+        match self {
+            SolvingError::Timeout(timeout) => {
+                SolvingError::Timeout(TimeoutError { stats, ..timeout })
+            }
+            error => error,
+        }
+    }
 }
 #[derive(Debug, Display, Error)]
 pub struct ParsingError;
+#[derive(From, Debug, Display, Error)]
+pub enum LoadingError {
+    Io(Error),
+    Parsing(ParsingError),
+    #[from(ignore)]
+    Invalid(#[error(not(source))] String),
+}
+#[derive(Debug, Display, Error)]
+#[display(fmt = "{} solver does not support {}", solver, feature)]
+pub struct UnsupportedError {
+    pub solver: &'static str,
+    pub feature: &'static str,
+}
+#[derive(Debug, Display, Error)]
+#[display(
+    fmt = "{} reached after {} candidates ({} solutions found)",
+    limit,
+    "stats.candidates",
+    "stats.solutions"
+)]
+pub struct TimeoutError {
+    pub limit: Limit,
+    /// Work done before the search stopped
+    pub stats: Stats,
+}
+/// The budget exhausted by a solver.
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq)]
+pub enum Limit {
+    #[display(fmt = "time limit of {:?}", _0)]
+    Time(Duration),
+    #[display(fmt = "limit of {} iterations", _0)]
+    Iterations(u64),
+}
 
-#[derive(Clone, Data, Lens)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct SolverState {
     pub params: Params,
 }
@@ -35,77 +87,376 @@ impl SolverState {
         }
     }
 
-    pub fn solve(&mut self) -> Result<(), SolvingError> {
-        self.params.solve_time.clear();
-        let time = Instant::now();
+    /// A puzzle with the default parameters read from `settings`.
+    pub fn from_settings(settings: &Settings) -> Self {
+        let mut solver_state = Self::new(
+            settings.rows,
+            settings.columns,
+            settings.states,
+            settings.objective,
+        );
+        solver_state.params.topology = settings.topology;
+        solver_state.params.tiling = settings.tiling;
+        solver_state.params.neighbourhood = settings.neighbourhood;
+        solver_state.params.radius = settings.radius;
+        solver_state.params.centre_weight = settings.centre_weight;
+        solver_state.params.neighbour_weight = settings.neighbour_weight;
+        solver_state.params.reset_grids();
+        solver_state
+    }
 
-        let solver: Box<dyn SolverTrait> = match crate::SETTINGS.solver {
+    pub fn solver(settings: &Settings) -> Box<dyn SolverTrait> {
+        let optimize = settings.optimize;
+        match settings.solver {
             Solver::Clingo { ref clingo_path } => Box::new(solver::Clingo {
                 clingo_path: clingo_path.clone(),
+                optimize,
             }),
-            Solver::Internal => Box::new(solver::Internal),
-            Solver::InternalPar { threads } => Box::new(solver::InternalPar { threads }),
-        };
-
-        let result = solver.solve(self);
+            Solver::Internal => Box::new(solver::Internal { optimize }),
+            Solver::InternalPar { threads } => Box::new(solver::InternalPar { threads, optimize }),
+            Solver::Linear => Box::new(solver::Linear { optimize }),
+            Solver::Bitset => Box::new(solver::Bitset { optimize }),
+        }
+    }
 
-        self.params.solve_time = format!("{:?}", time.elapsed());
+    /// Runs `solver` on the puzzle, showing the clicks it returns in the solution grid.
+    pub fn solve(
+        &mut self,
+        solver: &dyn SolverTrait,
+        control: &Control,
+    ) -> Result<Solution, SolvingError> {
+        self.clear_solution();
+        let result = solver.solve(&self.params.to_puzzle(), control);
+        self.set_solution(&result, solver.optimized());
         result
     }
 
+    /// Runs `solver` for every solution of the puzzle, showing the first one in the solution grid.
+    /// Returns whether there is any.
+    pub fn solve_all(
+        &mut self,
+        solver: &dyn SolverTrait,
+        control: &Control,
+    ) -> Result<bool, SolvingError> {
+        self.clear_solution();
+        let result = solver.solve_all(&self.params.to_puzzle(), control);
+        self.set_solutions(result)
+    }
+
+    /// Empties the solution grid and forgets the solutions found.
+    pub fn clear_solution(&mut self) {
+        self.params.solution.fill(0);
+        self.params.solutions = None;
+    }
+
+    /// Shows the result of a solver optimizing `optimized`, possibly run in the background.
+    pub fn set_solution(&mut self, result: &Result<Solution, SolvingError>, optimized: Optimize) {
+        if let Ok(solution) = result {
+            self.params.solution.set_cell_states(&solution.clicks);
+        }
+        self.params.optimized = optimized;
+    }
+
+    /// Shows the first of the solutions found, possibly in the background, returning whether there
+    /// is any.
+    pub fn set_solutions(
+        &mut self,
+        result: Result<Option<SolutionSet>, SolvingError>,
+    ) -> Result<bool, SolvingError> {
+        self.params.optimized = Optimize::None;
+        match result? {
+            Some(solutions) => {
+                self.params.solutions = Some(Arc::new(solutions));
+                self.params.show_solution(0);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Randomizes the puzzle with a new seed.
     pub fn randomize(&mut self) {
-        self.params.solve_time.clear();
-        Arc::make_mut(&mut self.params.puzzle.storage).fill(Cell {
-            state: self.params.objective,
-        });
-        self.params.puzzle.random_clicks();
+        self.randomize_with(rand::random());
+    }
+
+    /// Sets the puzzle to a configuration that is surely solvable, reached by random clicks from the
+    /// target. The clicks only depend on `seed` and the layout of the puzzle, which can thus be
+    /// shared as a seed.
+    pub fn randomize_with(&mut self, seed: u32) {
+        self.params.seed = Some(seed);
+        self.params.solutions = None;
+        let target = self.params.target.cell_states();
+        self.params.puzzle.set_cell_states(&target);
+        self.params.puzzle.random_clicks(seed);
     }
 }
 
-#[derive(Clone, Debug, Derivative, Data, Lens)]
-#[derivative(PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Params {
     pub rows: usize,
     pub columns: usize,
     pub states: usize,
-    #[derivative(PartialEq = "ignore")]
     pub objective: usize,
-    #[derivative(PartialEq = "ignore")]
+    pub topology: Topology,
+    pub tiling: Tiling,
+    pub neighbourhood: Neighbourhood,
+    pub radius: usize,
+    pub centre_weight: usize,
+    pub neighbour_weight: usize,
+    pub mask: Grid,
+    /// Graph whose nodes replace the cells of the grid
+    pub graph: Option<Arc<Graph>>,
     pub puzzle: Grid,
-    #[derivative(PartialEq = "ignore")]
     pub solution: Grid,
-    #[derivative(PartialEq = "ignore")]
-    pub play: bool,
-    #[derivative(PartialEq = "ignore")]
-    pub solve_time: String,
+    /// State each cell has to reach to solve the puzzle
+    pub target: Grid,
+    /// Seed of the last randomization of the puzzle, none once the puzzle is set otherwise
+    pub seed: Option<u32>,
+    /// Metric minimized by the solver of the solution grid
+    pub optimized: Optimize,
+    /// Every solution of the puzzle, once searched for
+    pub solutions: Option<Arc<SolutionSet>>,
 }
 impl Params {
-    fn new(rows: usize, columns: usize, states: usize, objective: usize) -> Self {
+    pub(crate) fn new(rows: usize, columns: usize, states: usize, objective: usize) -> Self {
         Self {
             rows,
             columns,
             states,
             objective,
-            play: false,
+            topology: Topology::Bounded,
+            tiling: Tiling::Square,
+            neighbourhood: Neighbourhood::Plus,
+            radius: 1,
+            centre_weight: 1,
+            neighbour_weight: 1,
+            mask: Stencil::default().to_mask(1, states),
+            graph: None,
+            seed: None,
             puzzle: Grid::new(rows, columns, states),
             solution: Grid::new(rows, columns, states),
-            solve_time: String::new(),
+            target: Grid::filled(rows, columns, states, objective),
+            optimized: Optimize::None,
+            solutions: None,
         }
     }
     pub fn reset_grids(&mut self) {
-        self.puzzle = Grid::new(self.rows, self.columns, self.states);
-        self.solution = Grid::new(self.rows, self.columns, self.states);
+        if self.mask.rows != 2 * self.radius + 1 || self.mask.states != self.states {
+            self.mask =
+                Stencil::from_mask(&self.mask, self.weights()).to_mask(self.radius, self.states);
+        }
+        self.puzzle = self.new_grid();
+        self.solution = self.new_grid();
+        self.target = self.new_grid();
+        self.reset_target();
+        self.solutions = None;
+        self.seed = None;
+    }
+
+    /// Whether both lay out their grids alike: same size, number of states, topology, tiling,
+    /// neighbourhood and graph, whatever the cells.
+    pub fn same_layout(&self, other: &Params) -> bool {
+        self.rows == other.rows
+            && self.columns == other.columns
+            && self.states == other.states
+            && self.topology == other.topology
+            && self.tiling == other.tiling
+            && self.neighbourhood == other.neighbourhood
+            && self.radius == other.radius
+            && self.centre_weight == other.centre_weight
+            && self.neighbour_weight == other.neighbour_weight
+            && self.graph == other.graph
+    }
+
+    /// Whether both describe the same puzzle, whatever their solutions.
+    pub fn same_puzzle(&self, other: &Params) -> bool {
+        self.same_layout(other)
+            && self.objective == other.objective
+            && self.mask.same_cells(&other.mask)
+            && self.puzzle.same_cells(&other.puzzle)
+            && self.target.same_cells(&other.target)
+    }
+
+    /// The puzzle the solvers work on.
+    pub fn to_puzzle(&self) -> Puzzle {
+        Puzzle {
+            grid: self.puzzle.clone(),
+            target: self.target.clone(),
+        }
+    }
+
+    /// Whether the puzzle can reach the target, found without solving it. Checking many puzzles
+    /// with the same layout is faster with a single `solver::Solvability`.
+    pub fn is_solvable(&self) -> bool {
+        Solvability::new(&self.puzzle).is_solvable(&self.puzzle, &self.target)
+    }
+
+    /// Sets the target state of every cell to `objective`.
+    pub fn reset_target(&mut self) {
+        self.target.fill(self.objective);
+    }
+
+    fn new_grid(&self) -> Grid {
+        let mut grid = Grid::new(self.rows, self.columns, self.states);
+        grid.topology = self.topology;
+        grid.tiling = self.tiling;
+        grid.stencil = self.stencil();
+        grid.graph = self.graph.clone();
+        grid
+    }
+
+    /// Replaces the grid with the graph read from `path`, laying its nodes out on a single row of
+    /// cells.
+    pub fn load_graph(&mut self, path: &str) -> Result<(), LoadingError> {
+        let graph = Graph::load(path)?;
+        self.rows = 1;
+        self.columns = graph.nodes();
+        self.graph = Some(Arc::new(graph));
+        self.reset_grids();
+        Ok(())
+    }
+
+    /// Goes back to a grid with as many cells as the nodes of the graph.
+    pub fn close_graph(&mut self) {
+        self.graph = None;
+        self.reset_grids();
+    }
+
+    pub fn stencil(&self) -> Stencil {
+        Stencil::new(self.neighbourhood, self.radius, &self.mask, self.weights())
+    }
+
+    pub fn weights(&self) -> Weights {
+        Weights {
+            centre: self.centre_weight,
+            neighbours: self.neighbour_weight,
+        }
+    }
+
+    /// Shows the solution numbered `index` by `SolutionSet::nth` in the solution grid.
+    pub fn show_solution(&mut self, index: usize) {
+        if let Some(solutions) = &self.solutions {
+            self.solution.set_cell_states(&solutions.nth(index));
+        }
+    }
+}
+
+/// A puzzle as the solvers see it: its cells, along with their layout and the cells affected by a
+/// click on each of them, and the state each cell has to reach.
+#[derive(Clone, Debug)]
+pub struct Puzzle {
+    pub grid: Grid,
+    /// State each cell has to reach, laid out like `grid`
+    pub target: Grid,
+}
+
+/// Outcome of a solver run.
+#[derive(Clone, Debug)]
+pub struct Solution {
+    /// Clicks to perform on each cell, row by row, all zero if the puzzle is not solvable
+    pub clicks: Vec<usize>,
+    pub total_clicks: usize,
+    pub solvable: bool,
+    pub stats: Stats,
+    pub time: Duration,
+}
+
+/// Work done by a solver to find a solution.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Click patterns checked, e.g. the seed clicks tried by the internal solvers
+    pub candidates: usize,
+    /// Solutions found among the candidates
+    pub solutions: usize,
+}
+
+impl Solution {
+    /// Wraps the `clicks` solving `puzzle`, if any.
+    pub fn new(puzzle: &Puzzle, clicks: Option<Vec<usize>>, stats: Stats, time: Duration) -> Self {
+        let solvable = clicks.is_some();
+        let clicks = clicks.unwrap_or_else(|| vec![0; puzzle.grid.storage.len()]);
+        Self {
+            total_clicks: clicks.iter().sum(),
+            solvable,
+            clicks,
+            stats,
+            time,
+        }
+    }
+}
+
+/// Every solution of a puzzle, described as a particular solution plus any combination of quiet
+/// patterns, i.e. click patterns that leave every cell unchanged.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SolutionSet {
+    pub particular: Vec<usize>,
+    pub quiet_patterns: Vec<QuietPattern>,
+    pub modulus: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuietPattern {
+    pub clicks: Vec<usize>,
+    /// Number of distinct multiples of the pattern
+    pub order: usize,
+}
+
+impl SolutionSet {
+    /// Builds the solution set from the exhaustive list of `solutions` of a puzzle, taking the
+    /// first one as the particular solution.
+    pub fn from_solutions(solutions: Vec<Vec<usize>>, modulus: usize) -> Option<Self> {
+        let (particular, others) = solutions.split_first()?;
+        let differences: Vec<Vec<usize>> = others
+            .iter()
+            .map(|other| {
+                other
+                    .iter()
+                    .zip(particular)
+                    .map(|(x, p)| (x + modulus - p) % modulus)
+                    .collect()
+            })
+            .collect();
+
+        Some(Self {
+            particular: particular.clone(),
+            quiet_patterns: solver::span(&differences, modulus),
+            modulus,
+        })
+    }
+
+    /// Number of distinct solutions, if it fits in a `usize`.
+    pub fn count(&self) -> Option<usize> {
+        self.quiet_patterns
+            .iter()
+            .try_fold(1usize, |acc, pattern| acc.checked_mul(pattern.order))
+    }
+
+    /// The solution obtained decoding `index` as the multiples of each quiet pattern to add to
+    /// the particular solution.
+    pub fn nth(&self, mut index: usize) -> Vec<usize> {
+        let mut clicks = self.particular.clone();
+        for pattern in &self.quiet_patterns {
+            let multiple = index % pattern.order;
+            index /= pattern.order;
+            for (x, y) in clicks.iter_mut().zip(&pattern.clicks) {
+                *x = (*x + multiple * y) % self.modulus;
+            }
+        }
+        clicks
     }
 }
 
-#[derive(Clone, Data)]
+#[derive(Clone, PartialEq)]
 pub struct Grid {
     pub(crate) rows: usize,
     pub(crate) columns: usize,
     pub(crate) states: usize,
+    pub(crate) topology: Topology,
+    pub(crate) tiling: Tiling,
+    pub(crate) stencil: Stencil,
+    /// Graph whose nodes are the cells of the only row of the grid
+    pub(crate) graph: Option<Arc<Graph>>,
     pub(crate) storage: Arc<Vec<Cell>>,
-    pub error: bool,
-    pub play: bool,
 }
 
 impl Grid {
@@ -114,55 +465,222 @@ impl Grid {
             rows,
             columns,
             states,
-            error: false,
-            play: false,
-            storage: Arc::new(vec![Cell::new(); rows * columns]),
+            topology: Topology::Bounded,
+            tiling: Tiling::Square,
+            stencil: Stencil::default(),
+            graph: None,
+            storage: Arc::new(vec![Cell::new(states); rows * columns]),
+        }
+    }
+
+    pub fn filled(rows: usize, columns: usize, states: usize, state: usize) -> Grid {
+        let mut grid = Grid::new(rows, columns, states);
+        grid.fill(state);
+        grid
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// Default number of states of the cells.
+    pub fn states(&self) -> usize {
+        self.states
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    pub fn tiling(&self) -> Tiling {
+        self.tiling
+    }
+
+    pub fn stencil(&self) -> &Stencil {
+        &self.stencil
+    }
+
+    pub fn set_stencil(&mut self, stencil: Stencil) {
+        self.stencil = stencil;
+    }
+
+    pub fn graph(&self) -> Option<&Arc<Graph>> {
+        self.graph.as_ref()
+    }
+
+    /// Sets every cell to `state`, keeping the disabled ones disabled.
+    pub fn fill(&mut self, state: usize) {
+        Arc::make_mut(&mut self.storage)
+            .iter_mut()
+            .for_each(|cell| cell.state = state);
+    }
+
+    /// Clicks the cell in `coord` `n` times, changing the state of every cell it affects.
+    pub fn click(&mut self, coord: GridCoord, n: usize) {
+        if !self[coord].disabled {
+            self.click_adjacent_unchecked(coord, n);
+        }
+    }
+
+    /// Changes the state of the cell in `coord` alone by `n`, as when editing the puzzle.
+    pub fn cycle_state(&mut self, coord: GridCoord, n: usize) {
+        let cell = &mut self[coord];
+        if !cell.disabled {
+            cell.state = (cell.state + n) % cell.modulus;
         }
     }
 
-    pub(crate) fn click(&mut self, coord: Option<GridCoord>, n: usize) {
-        if let Some(coord) = coord {
-            if self.play {
-                self.click_adjacent_unchecked(coord, n);
+    /// Cycles the number of states of the cell in `coord` through `2..=states`.
+    pub fn cycle_modulus(&mut self, coord: GridCoord, forward: bool) {
+        if self.states > 2 {
+            let states = self.states;
+            let cell = &mut self[coord];
+            cell.modulus = if forward {
+                (cell.modulus - 1) % (states - 1) + 2
             } else {
-                self[coord].state = (self[coord].state + n) % self.states;
-            }
+                (cell.modulus + states - 4) % (states - 1) + 2
+            };
+            cell.state %= cell.modulus;
         }
     }
 
+    /// Punches a hole in the grid at `coord`, or fills it back.
+    pub fn toggle_disabled(&mut self, coord: GridCoord) {
+        self[coord].disabled = !self[coord].disabled;
+    }
+
     pub(crate) fn click_adjacent_unchecked(&mut self, coord: GridCoord, n: usize) {
         let coords = self.adjacent(coord);
-        coords
-            .iter()
-            .for_each(|pos| self[*pos].state = (self[*pos].state + n) % self.states);
+        coords.iter().for_each(|&(pos, weight)| {
+            let cell = &mut self[pos];
+            cell.state = (cell.state + n * weight) % cell.modulus;
+        });
+    }
+
+    /// Offsets of the cells affected by a click on a cell of class `class`.
+    pub(crate) fn class_offsets(&self, class: usize) -> Vec<Offset> {
+        match self.tiling {
+            Tiling::Square => self.stencil.offsets().to_vec(),
+            tiling => tiling.edge_offsets(class, self.stencil.weights()),
+        }
+    }
+
+    /// Offsets of the cells affected by a click on `coord`.
+    pub(crate) fn offsets(&self, coord: GridCoord) -> Vec<Offset> {
+        self.class_offsets(self.tiling.class(coord))
     }
 
-    fn adjacent(&mut self, coord: GridCoord) -> Vec<GridCoord> {
+    /// Cells affected by a click on `coord`, each with the increment applied to it. Disabled cells
+    /// neither affect nor are affected by any other cell. Each node of a graph is affected once,
+    /// however many edges join it to the clicked one.
+    pub fn adjacent(&self, coord: GridCoord) -> Vec<(GridCoord, usize)> {
         let mut adj = vec![];
-        for i in -1..=1 {
-            for j in -1..=1 {
-                if i128::abs(i) + i128::abs(j) < 2 {
-                    let row_adj = coord.row as i128 + i;
-                    let col_adj = coord.col as i128 + j;
-                    if (0..self.rows as i128).contains(&row_adj)
-                        && (0..self.columns as i128).contains(&col_adj)
-                    {
-                        adj.push(GridCoord {
-                            row: row_adj as usize,
-                            col: col_adj as usize,
-                        });
-                    }
+        if self[coord].disabled {
+            return adj;
+        }
+        if let Some(graph) = &self.graph {
+            let weights = self.stencil.weights();
+            adj.push((coord, weights.centre));
+            for &node in graph.neighbours(coord.col) {
+                let adj_coord = GridCoord { row: 0, col: node };
+                if !self[adj_coord].disabled && adj.iter().all(|&(pos, _)| pos != adj_coord) {
+                    adj.push((adj_coord, weights.neighbours));
+                }
+            }
+            return adj;
+        }
+        for offset in self.offsets(coord) {
+            let mut row_adj = coord.row as isize + offset.row;
+            let mut col_adj = coord.col as isize + offset.col;
+            if self.topology.wraps_rows() {
+                row_adj = row_adj.rem_euclid(self.rows as isize);
+            }
+            if self.topology.wraps_columns() {
+                col_adj = col_adj.rem_euclid(self.columns as isize);
+            }
+            if (0..self.rows as isize).contains(&row_adj)
+                && (0..self.columns as isize).contains(&col_adj)
+            {
+                let adj_coord = GridCoord {
+                    row: row_adj as usize,
+                    col: col_adj as usize,
+                };
+                if self[adj_coord].disabled {
+                    continue;
+                }
+                // On narrow wrapped grids several offsets can reach the same cell, which receives
+                // the sum of their weights
+                match adj.iter_mut().find(|(pos, _)| *pos == adj_coord) {
+                    Some((_, weight)) => *weight += offset.weight,
+                    None => adj.push((adj_coord, offset.weight)),
                 }
             }
         }
         adj
     }
 
-    pub(crate) fn random_clicks(&mut self) {
-        let mut rng = rand::thread_rng();
+    fn same_cells(&self, other: &Grid) -> bool {
+        Arc::ptr_eq(&self.storage, &other.storage) || self.storage == other.storage
+    }
+
+    /// Whether each cell is disabled, along with its number of states.
+    pub fn layout(&self) -> Vec<(bool, usize)> {
+        self.storage
+            .iter()
+            .map(|cell| (cell.disabled, cell.modulus))
+            .collect()
+    }
+
+    pub fn set_layout(&mut self, layout: &[(bool, usize)]) {
+        Arc::make_mut(&mut self.storage)
+            .iter_mut()
+            .zip(layout)
+            .for_each(|(cell, &(disabled, modulus))| {
+                cell.disabled = disabled;
+                cell.modulus = modulus;
+                cell.state %= modulus;
+            });
+    }
+
+    /// Whether some enabled cells have a number of states other than `states`.
+    pub fn is_mixed(&self) -> bool {
+        self.storage
+            .iter()
+            .any(|cell| !cell.disabled && cell.modulus != self.states)
+    }
+
+    /// Least common multiple of the number of states of the enabled cells: clicking a cell this
+    /// many times leaves every cell unchanged.
+    pub fn modulus(&self) -> usize {
+        self.storage
+            .iter()
+            .filter(|cell| !cell.disabled)
+            .fold(1, |acc, cell| acc / gcd(acc, cell.modulus) * cell.modulus)
+    }
+
+    pub fn cell_states(&self) -> Vec<usize> {
+        self.storage.iter().map(|cell| cell.state).collect()
+    }
+
+    pub fn set_cell_states(&mut self, states: &[usize]) {
+        Arc::make_mut(&mut self.storage)
+            .iter_mut()
+            .zip(states)
+            .for_each(|(cell, &state)| cell.state = state);
+    }
+
+    /// Clicks every cell a random number of times, drawn from a generator seeded with `seed`.
+    pub fn random_clicks(&mut self, seed: u32) {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed.into());
+        // Drawn as 64-bit numbers for the seed to give the same puzzle whatever the platform
+        let modulus = self.modulus() as u64;
         for row in 0..self.rows {
             for col in 0..self.columns {
-                let n = rng.gen_range(0..self.states);
+                let n = rng.gen_range(0..modulus) as usize;
                 self.click_adjacent_unchecked(GridCoord { row, col }, n);
             }
         }
@@ -199,19 +717,70 @@ impl Debug for Grid {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Data)]
-pub(crate) struct GridCoord {
-    pub(crate) row: usize,
-    pub(crate) col: usize,
+/// How the edges of a grid are joined together.
+#[derive(EnumString, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Topology {
+    /// Cells on the edges have no neighbours beyond them
+    #[default]
+    Bounded,
+    /// Both the left and right edges and the top and bottom edges are joined
+    Torus,
+    /// The left and right edges are joined, so the grid wraps horizontally
+    HorizontalCylinder,
+    /// The top and bottom edges are joined, so the grid wraps vertically
+    VerticalCylinder,
+}
+
+impl Topology {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Topology::Bounded => "Bounded",
+            Topology::Torus => "Torus",
+            Topology::HorizontalCylinder => "Horizontal cylinder",
+            Topology::VerticalCylinder => "Vertical cylinder",
+        }
+    }
+
+    pub fn wraps_rows(&self) -> bool {
+        matches!(self, Topology::Torus | Topology::VerticalCylinder)
+    }
+
+    pub fn wraps_columns(&self) -> bool {
+        matches!(self, Topology::Torus | Topology::HorizontalCylinder)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GridCoord {
+    pub row: usize,
+    pub col: usize,
 }
 
-#[derive(Clone, Debug, Data)]
-pub(crate) struct Cell {
-    pub(crate) state: usize,
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cell {
+    pub state: usize,
+    /// Number of states the cell cycles through
+    pub modulus: usize,
+    /// Whether the cell is a hole in the grid
+    pub disabled: bool,
 }
 
 impl Cell {
-    fn new() -> Self {
-        Self { state: 0 }
+    fn new(modulus: usize) -> Self {
+        Self {
+            state: 0,
+            modulus,
+            disabled: false,
+        }
+    }
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
     }
 }
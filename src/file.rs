@@ -0,0 +1,591 @@
+//! Puzzle files, TOML documents holding the parameters of a puzzle and its grids:
+//!
+//! ```toml
+//! rows = 2
+//! columns = 3
+//! states = 3
+//! objective = 0
+//! topology = "bounded"
+//! tiling = "square"
+//! neighbourhood = "plus"
+//! radius = 1
+//! centre_weight = 1
+//! neighbour_weight = 1
+//! # Optional: seed the puzzle was randomized with, unless edited since
+//! seed = 12345
+//! # The cell states, one row per line, `-` marking disabled cells
+//! puzzle = """
+//! 1 0 2
+//! - 1 0
+//! """
+//! # Optional: states to reach if not all `objective`, cells with their own number of states,
+//! # the solution, the cells affected by a click on custom neighbourhoods and the graph file
+//! # whose nodes replace the cells
+//! target = "..."
+//! cell_states = "..."
+//! solution = "..."
+//! mask = "..."
+//! graph = "..."
+//! ```
+use crate::data::{Cell, Grid, GridCoord, LoadingError, Params, Topology};
+use crate::neighbourhood::{Neighbourhood, Tiling};
+use derive_more::{Display, Error, From};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Cells of the biggest grid that can be read, keeping files and pasted text from allocating huge
+/// grids
+pub const MAX_CELLS: usize = 1 << 20;
+/// Highest number of states (and weight of a click) a puzzle file can have
+pub const MAX_STATES: usize = 256;
+
+#[derive(From, Debug, Display, Error)]
+pub enum FileError {
+    Io(std::io::Error),
+    Syntax(toml::de::Error),
+    Serialization(toml::ser::Error),
+    #[display(fmt = "cannot load graph: {}", _0)]
+    Graph(LoadingError),
+    #[from(ignore)]
+    Invalid(#[error(not(source))] String),
+}
+
+#[derive(Serialize, Deserialize)]
+struct PuzzleFile {
+    rows: usize,
+    columns: usize,
+    states: usize,
+    objective: usize,
+    topology: Topology,
+    tiling: Tiling,
+    neighbourhood: Neighbourhood,
+    radius: usize,
+    centre_weight: usize,
+    neighbour_weight: usize,
+    #[serde(default)]
+    seed: Option<u32>,
+    puzzle: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cell_states: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    solution: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mask: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    graph: Option<String>,
+}
+
+impl Params {
+    /// Writes a puzzle file, the path of its graph (if any) being relative to the directory of the
+    /// file if the graph is in it, and absolute otherwise.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), FileError> {
+        let path = path.as_ref();
+        let text = self.toml_in(path.parent().unwrap_or(Path::new("")))?;
+        Ok(std::fs::write(path, text)?)
+    }
+
+    /// Reads a puzzle file, the path of its graph (if any) being relative to the directory of the
+    /// file.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, FileError> {
+        let path = path.as_ref();
+        let file = toml::from_str(&std::fs::read_to_string(path)?)?;
+        Self::from_file(file, path.parent().unwrap_or(Path::new("")))
+    }
+
+    pub fn to_toml(&self) -> Result<String, FileError> {
+        self.toml_in(Path::new(""))
+    }
+
+    /// The puzzle file written in `dir`.
+    fn toml_in(&self, dir: &Path) -> Result<String, FileError> {
+        let objective = self.objective;
+        let file = PuzzleFile {
+            rows: self.rows,
+            columns: self.columns,
+            states: self.states,
+            objective,
+            topology: self.topology,
+            tiling: self.tiling,
+            neighbourhood: self.neighbourhood,
+            radius: self.radius,
+            centre_weight: self.centre_weight,
+            neighbour_weight: self.neighbour_weight,
+            seed: self.seed,
+            puzzle: self.puzzle.to_text(),
+            target: self
+                .target
+                .storage
+                .iter()
+                .any(|cell| !cell.disabled && cell.state != objective)
+                .then(|| self.target.to_text()),
+            cell_states: self
+                .puzzle
+                .is_mixed()
+                .then(|| grid_text(&self.puzzle, |cell| cell.modulus)),
+            solution: self
+                .solution
+                .storage
+                .iter()
+                .any(|c| c.state > 0)
+                .then(|| self.solution.to_text()),
+            mask: (self.neighbourhood == Neighbourhood::Custom).then(|| self.mask.to_text()),
+            graph: self
+                .graph
+                .as_ref()
+                .map(|graph| graph_path(&graph.path, dir)),
+        };
+        Ok(toml::to_string_pretty(&file)?)
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, FileError> {
+        Self::from_file(toml::from_str(text)?, Path::new(""))
+    }
+
+    /// Replaces the puzzle with `cells`, resizing the grids to fit them, or listing the nodes in
+    /// order if the puzzle is played on a graph.
+    pub fn set_puzzle(&mut self, cells: &[Vec<Option<usize>>]) -> Result<(), FileError> {
+        if cells.first().is_none_or(Vec::is_empty) {
+            return Err(invalid("no cells"));
+        }
+        if self.graph.is_some() {
+            let cells: Vec<Option<usize>> = cells.iter().flatten().copied().collect();
+            if cells.len() != self.columns {
+                return Err(FileError::Invalid(format!(
+                    "the puzzle lists {} states, but the graph has {} nodes",
+                    cells.len(),
+                    self.columns
+                )));
+            }
+            self.reset_grids();
+            self.puzzle.set_cells(&[cells])?;
+        } else {
+            self.rows = cells.len();
+            self.columns = cells[0].len();
+            self.reset_grids();
+            self.puzzle.set_cells(cells)?;
+        }
+        // Copy the holes to the other grids
+        let layout = self.puzzle.layout();
+        self.target.set_layout(&layout);
+        self.solution.set_layout(&layout);
+        Ok(())
+    }
+
+    fn from_file(file: PuzzleFile, dir: &Path) -> Result<Self, FileError> {
+        if !(2..=MAX_STATES).contains(&file.states) {
+            return Err(FileError::Invalid(format!(
+                "the number of states must be from 2 to {}",
+                MAX_STATES
+            )));
+        }
+        // The size is checked against the puzzle grid before allocating any grid
+        let cells = parse_cells(&file.puzzle).map_err(|e| field("puzzle", e))?;
+        if (cells.len(), cells[0].len()) != (file.rows, file.columns) {
+            return Err(FileError::Invalid(format!(
+                "the puzzle has {} rows of {} cells instead of {} rows of {}",
+                cells.len(),
+                cells[0].len(),
+                file.rows,
+                file.columns
+            )));
+        }
+        if file.rows * file.columns > MAX_CELLS {
+            return Err(FileError::Invalid(format!(
+                "the puzzle has more than {} cells",
+                MAX_CELLS
+            )));
+        }
+        let side = 2 * file.radius.min(MAX_CELLS) + 1;
+        if file.radius == 0 || file.radius > file.rows.max(file.columns) || side * side > MAX_CELLS
+        {
+            return Err(invalid(
+                "the radius must be positive and fit the size of the grid",
+            ));
+        }
+        if file.centre_weight > MAX_STATES || file.neighbour_weight > MAX_STATES {
+            return Err(FileError::Invalid(format!(
+                "the weights cannot exceed {}",
+                MAX_STATES
+            )));
+        }
+        if file.objective >= file.states {
+            return Err(invalid(
+                "the objective must be lower than the number of states",
+            ));
+        }
+
+        let mut params = Params::new(file.rows, file.columns, file.states, file.objective);
+        params.topology = file.topology;
+        params.tiling = file.tiling;
+        params.neighbourhood = file.neighbourhood;
+        params.radius = file.radius;
+        params.centre_weight = file.centre_weight;
+        params.neighbour_weight = file.neighbour_weight;
+        if let Some(mask) = &file.mask {
+            params.mask = Grid::new(side, side, file.states);
+            params.mask.read_text(mask).map_err(|e| field("mask", e))?;
+        }
+        match &file.graph {
+            Some(graph) => {
+                params.load_graph(&dir.join(graph).to_string_lossy())?;
+                if file.rows != 1 || file.columns != params.columns {
+                    return Err(FileError::Invalid(format!(
+                        "the graph has {} nodes, but the puzzle has {} rows of {} cells",
+                        params.columns, file.rows, file.columns
+                    )));
+                }
+            }
+            None => params.reset_grids(),
+        }
+
+        if let Some(cell_states) = &file.cell_states {
+            let cells = parse_cells(cell_states).map_err(|e| field("cell_states", e))?;
+            set_cell_moduli(&mut params.puzzle, &cells).map_err(|e| field("cell_states", e))?;
+        }
+        params
+            .puzzle
+            .set_cells(&cells)
+            .map_err(|e| field("puzzle", e))?;
+        // The other grids share the holes and the number of states of the puzzle cells
+        let layout = params.puzzle.layout();
+        params.target.set_layout(&layout);
+        params.solution.set_layout(&layout);
+        if let Some(target) = &file.target {
+            params
+                .target
+                .read_text(target)
+                .map_err(|e| field("target", e))?;
+        }
+        if let Some(solution) = &file.solution {
+            let cells = parse_cells(solution).map_err(|e| field("solution", e))?;
+            set_clicks(&mut params.solution, &cells).map_err(|e| field("solution", e))?;
+        }
+        params.seed = file.seed;
+        Ok(params)
+    }
+}
+
+impl Grid {
+    /// The cell states, one row per line, with `-` marking disabled cells.
+    pub fn to_text(&self) -> String {
+        grid_text(self, |cell| cell.state)
+    }
+
+    /// Sets the cell states (and the disabled cells) from `text`, written as by `to_text`.
+    pub fn read_text(&mut self, text: &str) -> Result<(), FileError> {
+        let cells = parse_cells(text)?;
+        check_size(self, &cells)?;
+        self.set_cells(&cells)
+    }
+
+    /// Sets the states of the cells listed row by row, `None` disabling a cell.
+    pub fn set_cells(&mut self, cells: &[Vec<Option<usize>>]) -> Result<(), FileError> {
+        for (row, states) in cells.iter().enumerate() {
+            for (col, &state) in states.iter().enumerate() {
+                let cell = &mut self[GridCoord { row, col }];
+                match state {
+                    Some(state) if state < cell.modulus => cell.state = state,
+                    Some(state) => {
+                        return Err(FileError::Invalid(format!(
+                            "row {}, column {}: invalid state {} for a cell with {} states",
+                            row + 1,
+                            col + 1,
+                            state,
+                            cell.modulus
+                        )))
+                    }
+                    None => cell.disabled = true,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads the rows of a grid written as by `Grid::to_text`, `None` standing for a disabled cell.
+/// Blank lines are skipped and `#` starts a comment.
+pub fn parse_cells(text: &str) -> Result<Vec<Vec<Option<usize>>>, FileError> {
+    let cells = text
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(row, line)| {
+            line.split_whitespace()
+                .map(|cell| match cell {
+                    "-" => Ok(None),
+                    cell => cell.parse().map(Some).map_err(|_| {
+                        FileError::Invalid(format!("row {}: invalid cell `{}`", row + 1, cell))
+                    }),
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let Some(first) = cells.first() else {
+        return Err(invalid("no cells"));
+    };
+    if let Some(row) = cells.iter().position(|row| row.len() != first.len()) {
+        return Err(FileError::Invalid(format!(
+            "row {} has {} cells instead of {}",
+            row + 1,
+            cells[row].len(),
+            first.len()
+        )));
+    }
+    Ok(cells)
+}
+
+/// Path of the graph file at `path` as seen from `dir`: relative to `dir` if the graph is in it,
+/// absolute otherwise.
+fn graph_path(path: &str, dir: &Path) -> String {
+    // Text without a file, or a file in the working directory, sees the graph as the program does
+    if dir.as_os_str().is_empty() {
+        return path.to_owned();
+    }
+    let Ok(graph) = Path::new(path).canonicalize() else {
+        return path.to_owned();
+    };
+    let relative = dir
+        .canonicalize()
+        .ok()
+        .and_then(|dir| graph.strip_prefix(dir).ok().map(Path::to_path_buf));
+    relative.unwrap_or(graph).to_string_lossy().into_owned()
+}
+
+fn grid_text(grid: &Grid, value: impl Fn(&Cell) -> usize) -> String {
+    let mut text = String::new();
+    for row in 0..grid.rows {
+        let line: Vec<String> = (0..grid.columns)
+            .map(|col| {
+                let cell = &grid[GridCoord { row, col }];
+                if cell.disabled {
+                    String::from("-")
+                } else {
+                    value(cell).to_string()
+                }
+            })
+            .collect();
+        text += &line.join(" ");
+        text += "\n";
+    }
+    text
+}
+
+/// Gives cells their own number of states, from 2 to the states of the puzzle.
+fn set_cell_moduli(grid: &mut Grid, cells: &[Vec<Option<usize>>]) -> Result<(), FileError> {
+    check_size(grid, cells)?;
+    let states = grid.states;
+    for (row, moduli) in cells.iter().enumerate() {
+        for (col, &modulus) in moduli.iter().enumerate() {
+            match modulus {
+                Some(modulus) if (2..=states).contains(&modulus) => {
+                    grid[GridCoord { row, col }].modulus = modulus;
+                }
+                Some(modulus) => {
+                    return Err(FileError::Invalid(format!(
+                        "row {}, column {}: invalid number of states {}",
+                        row + 1,
+                        col + 1,
+                        modulus
+                    )))
+                }
+                None => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sets the clicks of a solution, which can exceed the states of a cell when the puzzle mixes
+/// cells with different numbers of states.
+fn set_clicks(solution: &mut Grid, cells: &[Vec<Option<usize>>]) -> Result<(), FileError> {
+    check_size(solution, cells)?;
+    let modulus = solution.modulus();
+    let clicks: Vec<usize> = cells.iter().flatten().map(|c| c.unwrap_or(0)).collect();
+    if let Some(&click) = clicks.iter().find(|&&click| click >= modulus) {
+        return Err(FileError::Invalid(format!(
+            "invalid number of clicks {}",
+            click
+        )));
+    }
+    solution.set_cell_states(&clicks);
+    Ok(())
+}
+
+fn check_size(grid: &Grid, cells: &[Vec<Option<usize>>]) -> Result<(), FileError> {
+    if cells.len() == grid.rows && cells[0].len() == grid.columns {
+        Ok(())
+    } else {
+        Err(FileError::Invalid(format!(
+            "expected {} rows of {} cells, found {} rows of {}",
+            grid.rows,
+            grid.columns,
+            cells.len(),
+            cells[0].len()
+        )))
+    }
+}
+
+fn invalid(message: &str) -> FileError {
+    FileError::Invalid(String::from(message))
+}
+
+/// Tells which grid of the file `error` was found in.
+fn field(name: &str, error: FileError) -> FileError {
+    match error {
+        FileError::Invalid(message) => FileError::Invalid(format!("{}: {}", name, message)),
+        error => error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    /// A puzzle filling every field of the files, with holes and cells of their own number of
+    /// states.
+    fn full_params() -> Params {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut params = Params::new(3, 4, 6, 0);
+        params.topology = Topology::Torus;
+        params.neighbourhood = Neighbourhood::Custom;
+        params.centre_weight = 2;
+        params.neighbour_weight = 3;
+        let mask: Vec<usize> = (0..9).map(|_| rng.gen_range(0..6)).collect();
+        params.mask.set_cell_states(&mask);
+        params.reset_grids();
+
+        let layout: Vec<(bool, usize)> = (0..12)
+            .map(|i| match i % 4 {
+                1 => (true, 6),
+                _ => (false, [2, 3, 6][rng.gen_range(0..3)]),
+            })
+            .collect();
+        let modulus = 6;
+        let mut states = |modulus: &dyn Fn(usize) -> usize| -> Vec<usize> {
+            layout
+                .iter()
+                .enumerate()
+                .map(|(i, &(disabled, _))| match disabled {
+                    true => 0,
+                    false => rng.gen_range(0..modulus(i)),
+                })
+                .collect()
+        };
+        let (puzzle, target) = (states(&|i| layout[i].1), states(&|i| layout[i].1));
+        let solution = states(&|_| modulus);
+        for (grid, states) in [
+            (&mut params.puzzle, puzzle),
+            (&mut params.target, target),
+            (&mut params.solution, solution),
+        ] {
+            grid.set_layout(&layout);
+            grid.set_cell_states(&states);
+        }
+        params.seed = Some(12345);
+        params
+    }
+
+    /// A directory of its own for each test writing files.
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("lights_out_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn reads_what_it_writes() {
+        let params = full_params();
+        let text = params.to_toml().unwrap();
+        for field in ["seed", "target", "cell_states", "solution", "mask"] {
+            assert!(text.contains(&format!("{} = ", field)), "{}", text);
+        }
+        assert_eq!(Params::from_toml(&text).unwrap(), params);
+
+        let dir = temp_dir("reads_what_it_writes");
+        let path = dir.join("puzzle.toml");
+        params.save(&path).unwrap();
+        let read = Params::open(&path);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(read.unwrap(), params);
+    }
+
+    #[test]
+    fn finds_graph_from_file() {
+        let dir = temp_dir("finds_graph_from_file");
+        std::fs::create_dir_all(dir.join("puzzles")).unwrap();
+        let graph = dir.join("graph.txt");
+        std::fs::write(&graph, "nodes 3\nedge 0 1\nedge 1 2").unwrap();
+        let mut params = Params::new(1, 1, 3, 0);
+        params.load_graph(&graph.to_string_lossy()).unwrap();
+        params.puzzle.set_cell_states(&[2, 0, 1]);
+
+        // Next to the graph its path is relative, and elsewhere absolute
+        let next_to_graph = dir.join("puzzle.toml");
+        params.save(&next_to_graph).unwrap();
+        let text = std::fs::read_to_string(&next_to_graph).unwrap();
+        let elsewhere = dir.join("puzzles").join("puzzle.toml");
+        params.save(&elsewhere).unwrap();
+        let reads = [Params::open(&next_to_graph), Params::open(&elsewhere)];
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(text.contains("graph = 'graph.txt'"), "{}", text);
+        for read in reads {
+            let read = read.unwrap();
+            assert_eq!(read.puzzle.cell_states(), [2, 0, 1]);
+            assert_eq!(
+                read.graph.unwrap().edges,
+                params.graph.as_ref().unwrap().edges
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        let file = |states: usize, radius: usize, weight: usize, grids: &str| {
+            format!(
+                "rows = 1\ncolumns = 1024\nstates = {}\nobjective = 0\ntopology = \"bounded\"\n\
+                 tiling = \"square\"\nneighbourhood = \"plus\"\nradius = {}\ncentre_weight = {}\n\
+                 neighbour_weight = 1\n{}",
+                states, radius, weight, grids
+            )
+        };
+        let row =
+            |cell: &str, cells: usize| format!("\"\"\"\n{}\n\"\"\"", vec![cell; cells].join(" "));
+        let puzzle = format!("puzzle = {}\n", row("0", 1024));
+        let huge = format!("rows = 1\ncolumns = {}\n", MAX_CELLS + 1);
+        for text in [
+            file(1, 1, 1, &puzzle),
+            file(MAX_STATES + 1, 1, 1, &puzzle),
+            // The side of the neighbourhood squared exceeds the cells of the biggest grid
+            file(2, 600, 1, &puzzle),
+            file(2, 1, MAX_STATES + 1, &puzzle),
+            file(2, 1, 1, &format!("puzzle = {}\n", row("2", 1024))),
+            file(
+                3,
+                1,
+                1,
+                &format!("{}cell_states = {}\n", puzzle, row("4", 1024)),
+            ),
+            file(
+                2,
+                1,
+                1,
+                &format!("{}solution = {}\n", puzzle, row("2", 1024)),
+            ),
+            file(2, 1, 1, &format!("puzzle = {}\n", row("0", MAX_CELLS + 1))).replacen(
+                "rows = 1\ncolumns = 1024\n",
+                &huge,
+                1,
+            ),
+        ] {
+            let result = Params::from_toml(&text);
+            assert!(matches!(result, Err(FileError::Invalid(_))), "{}", text);
+        }
+    }
+}
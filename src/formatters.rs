@@ -2,7 +2,7 @@ use druid::text::{
     format::{Formatter, Validation, ValidationError},
     Selection,
 };
-use std::num::NonZeroUsize;
+use std::num::{NonZeroUsize, ParseIntError};
 
 pub struct NonZeroFormatter;
 
@@ -25,3 +25,32 @@ impl Formatter<usize> for NonZeroFormatter {
         Ok(num.into())
     }
 }
+
+/// Formats a seed, leaving the text box empty if there is none.
+pub struct SeedFormatter;
+
+impl SeedFormatter {
+    fn parse(input: &str) -> Result<Option<u32>, ParseIntError> {
+        match input.trim() {
+            "" => Ok(None),
+            input => input.parse().map(Some),
+        }
+    }
+}
+
+impl Formatter<Option<u32>> for SeedFormatter {
+    fn format(&self, value: &Option<u32>) -> String {
+        value.map(|seed| seed.to_string()).unwrap_or_default()
+    }
+
+    fn validate_partial_input(&self, input: &str, _sel: &Selection) -> Validation {
+        match Self::parse(input) {
+            Ok(_) => Validation::success(),
+            Err(e) => Validation::failure(e),
+        }
+    }
+
+    fn value(&self, input: &str) -> Result<Option<u32>, ValidationError> {
+        Self::parse(input).map_err(ValidationError::new)
+    }
+}
@@ -0,0 +1,282 @@
+//! Puzzles generated to a difficulty, rated by their optimal solution: the more clicks (or pressed
+//! cells) the best solution takes, the harder the puzzle.
+//!
+//! A puzzle is generated by drawing a random solution and clicking its opposite from the target,
+//! which makes it surely solvable. Its solutions are the drawn one plus any combination of quiet
+//! patterns, among which the optimal one is looked for.
+use crate::data::{GridCoord, Params, QuietPattern, SolutionSet, SolverState, SolvingError};
+use crate::settings::Optimize;
+use crate::solver::{Control, Linear, Solver};
+use derive_more::{Display, Error, From};
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use strum::{EnumIter, EnumString};
+
+/// Random click patterns tried before giving up
+const ATTEMPTS: usize = 1000;
+/// Solutions of a click pattern the optimal one is looked for among, more making puzzles too slow
+/// to rate
+const MAX_SOLUTIONS: usize = 4096;
+
+#[derive(From, Debug, Display, Error)]
+pub enum GenerationError {
+    Solving(SolvingError),
+    #[display(fmt = "the difficulty must be measured in clicks or pressed cells")]
+    NoMetric,
+    #[display(fmt = "the lowest cost of the goal exceeds the highest one")]
+    EmptyRange,
+    #[display(fmt = "puzzles with this layout have too many solutions to be rated")]
+    TooManySolutions,
+    #[display(
+        fmt = "no puzzle of the requested difficulty found in {} attempts",
+        ATTEMPTS
+    )]
+    NotFound,
+}
+
+/// What a generated puzzle must look like.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Goal {
+    /// How the optimal solution is rated, either `Optimize::Clicks` or `Optimize::PressedCells`
+    pub metric: Optimize,
+    /// Lowest cost of the optimal solution
+    pub min: usize,
+    /// Highest cost of the optimal solution
+    pub max: usize,
+    /// Whether no other solution can cost as little as the optimal one
+    pub unique: bool,
+}
+
+#[derive(EnumString, EnumIter, Clone, Copy, Debug, PartialEq, Eq)]
+#[strum(serialize_all = "snake_case")]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Medium => "Medium",
+            Difficulty::Hard => "Hard",
+        }
+    }
+
+    /// The goal of this difficulty on a puzzle laid out like `params`, the optimal solution
+    /// costing a share of the enabled cells.
+    pub fn goal(&self, params: &Params, metric: Optimize, unique: bool) -> Goal {
+        let cells = params.puzzle.storage.iter().filter(|c| !c.disabled).count();
+        let (low, high) = match self {
+            Difficulty::Easy => (0.0, 0.2),
+            Difficulty::Medium => (0.2, 0.4),
+            Difficulty::Hard => (0.4, 1.0),
+        };
+        let min = ((low * cells as f64).ceil() as usize).max(1);
+        let max = ((high * cells as f64).floor() as usize).max(min);
+        Goal {
+            metric,
+            min,
+            max,
+            unique,
+        }
+    }
+}
+
+impl SolverState {
+    /// Sets the puzzle to one reaching `goal`, keeping its layout and target, and returns the cost
+    /// of its optimal solution. The puzzle only depends on `seed` and the layout.
+    pub fn generate(
+        &mut self,
+        goal: &Goal,
+        seed: u32,
+        control: &Control,
+    ) -> Result<usize, GenerationError> {
+        if goal.metric == Optimize::None {
+            return Err(GenerationError::NoMetric);
+        }
+        if goal.min > goal.max {
+            return Err(GenerationError::EmptyRange);
+        }
+        let quiet_patterns = self.quiet_patterns(control)?;
+        let modulus = self.params.puzzle.modulus();
+        let count = quiet_patterns
+            .iter()
+            .try_fold(1usize, |acc, pattern| acc.checked_mul(pattern.order))
+            .filter(|&count| count <= MAX_SOLUTIONS)
+            .ok_or(GenerationError::TooManySolutions)?;
+        let cells = &self.params.puzzle.storage;
+        let enabled: Vec<usize> = (0..cells.len()).filter(|&i| !cells[i].disabled).collect();
+        if enabled.is_empty() {
+            return Err(GenerationError::NotFound);
+        }
+
+        let mut rng = ChaCha8Rng::seed_from_u64(seed.into());
+        control.start((ATTEMPTS * count) as u64);
+        for _ in 0..ATTEMPTS {
+            let clicks = random_pattern(goal, &enabled, cells.len(), modulus, &mut rng);
+            let solutions = SolutionSet {
+                particular: clicks.clone(),
+                quiet_patterns: quiet_patterns.clone(),
+                modulus,
+            };
+            let mut best = (usize::MAX, 0);
+            for index in 0..count {
+                control.advance(1)?;
+                let cost = goal.metric.cost(&solutions.nth(index));
+                if cost < best.0 {
+                    best = (cost, 1);
+                } else if cost == best.0 {
+                    best.1 += 1;
+                }
+            }
+            let (cost, optimal) = best;
+            if (goal.min..=goal.max).contains(&cost) && (!goal.unique || optimal == 1) {
+                // The opposite of the solution takes the target to the puzzle
+                let opposite: Vec<usize> = clicks.iter().map(|c| (modulus - c) % modulus).collect();
+                self.set_clicked(&opposite);
+                return Ok(cost);
+            }
+        }
+        Err(GenerationError::NotFound)
+    }
+
+    /// The click patterns leaving the puzzle unchanged.
+    fn quiet_patterns(&self, control: &Control) -> Result<Vec<QuietPattern>, GenerationError> {
+        let mut solved = self.params.to_puzzle();
+        solved.grid.set_cell_states(&solved.target.cell_states());
+        let solutions = Linear {
+            optimize: Optimize::None,
+        }
+        .solve_all(&solved, control)?;
+        Ok(solutions.map(|s| s.quiet_patterns).unwrap_or_default())
+    }
+
+    /// Sets the puzzle to the target clicked `clicks` times on each cell.
+    fn set_clicked(&mut self, clicks: &[usize]) {
+        self.params.seed = None;
+        self.params.solutions = None;
+        let target = self.params.target.cell_states();
+        let puzzle = &mut self.params.puzzle;
+        puzzle.set_cell_states(&target);
+        for (i, &n) in clicks.iter().enumerate() {
+            if n > 0 {
+                let coord = GridCoord {
+                    row: i / puzzle.columns,
+                    col: i % puzzle.columns,
+                };
+                puzzle.click_adjacent_unchecked(coord, n);
+            }
+        }
+    }
+}
+
+/// A solution clicking `enabled` cells as many times (or as many cells) as `goal` allows, which
+/// bounds the cost of the optimal solution from above.
+fn random_pattern(
+    goal: &Goal,
+    enabled: &[usize],
+    cells: usize,
+    modulus: usize,
+    rng: &mut impl Rng,
+) -> Vec<usize> {
+    let mut clicks = vec![0; cells];
+    match goal.metric {
+        Optimize::PressedCells => {
+            let cost = rng.gen_range(goal.min..=goal.max).min(enabled.len());
+            for &i in enabled.choose_multiple(rng, cost) {
+                clicks[i] = rng.gen_range(1..modulus);
+            }
+        }
+        _ => {
+            let cost = rng
+                .gen_range(goal.min..=goal.max)
+                .min(enabled.len() * (modulus - 1));
+            let mut clicked = 0;
+            while clicked < cost {
+                let &i = enabled.choose(rng).unwrap();
+                if clicks[i] < modulus - 1 {
+                    clicks[i] += 1;
+                    clicked += 1;
+                }
+            }
+        }
+    }
+    clicks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use strum::IntoEnumIterator;
+
+    /// The cost of the optimal solutions of the puzzle, and how many solutions cost as much.
+    fn optimal(solver_state: &SolverState, metric: Optimize) -> (usize, usize) {
+        let solutions = Linear {
+            optimize: Optimize::None,
+        }
+        .solve_all(&solver_state.params.to_puzzle(), &Control::new())
+        .unwrap()
+        .unwrap();
+        let costs: Vec<usize> = (0..solutions.count().unwrap())
+            .map(|index| metric.cost(&solutions.nth(index)))
+            .collect();
+        let cost = *costs.iter().min().unwrap();
+        (cost, costs.iter().filter(|&&c| c == cost).count())
+    }
+
+    #[test]
+    fn reaches_goal() {
+        for (rows, columns, states) in [(5, 5, 2), (4, 4, 2), (3, 4, 3)] {
+            let mut solver_state = SolverState::new(rows, columns, states, 0);
+            solver_state.params.reset_grids();
+            let mut generated = 0;
+            for difficulty in Difficulty::iter() {
+                for metric in [Optimize::Clicks, Optimize::PressedCells] {
+                    for unique in [false, true] {
+                        let goal = difficulty.goal(&solver_state.params, metric, unique);
+                        // Some goals cannot be reached, e.g. a single optimal solution when every
+                        // puzzle has several
+                        let cost = match solver_state.generate(&goal, 7, &Control::new()) {
+                            Err(GenerationError::NotFound) => continue,
+                            result => result.unwrap(),
+                        };
+                        generated += 1;
+                        let (optimal_cost, optimal) = optimal(&solver_state, metric);
+                        assert_eq!(cost, optimal_cost, "{:?}", goal);
+                        assert!((goal.min..=goal.max).contains(&cost), "{:?}", goal);
+                        assert!(!unique || optimal == 1, "{:?}", goal);
+
+                        let cost = Linear { optimize: metric }
+                            .solve(&solver_state.params.to_puzzle(), &Control::new())
+                            .map(|solution| metric.cost(&solution.clicks))
+                            .unwrap();
+                        assert_eq!(cost, optimal_cost, "{:?}", goal);
+                    }
+                }
+            }
+            assert!(
+                generated >= 6,
+                "{}x{}: {} puzzles",
+                rows,
+                columns,
+                generated
+            );
+        }
+    }
+
+    #[test]
+    fn same_seed_same_puzzle() {
+        let mut solver_state = SolverState::new(5, 5, 2, 0);
+        solver_state.params.reset_grids();
+        let goal = Difficulty::Medium.goal(&solver_state.params, Optimize::Clicks, false);
+        let mut puzzles = vec![];
+        for seed in [3, 3, 4] {
+            solver_state.generate(&goal, seed, &Control::new()).unwrap();
+            puzzles.push(solver_state.params.puzzle.cell_states());
+        }
+        assert_eq!(puzzles[0], puzzles[1]);
+        assert_ne!(puzzles[0], puzzles[2]);
+    }
+}
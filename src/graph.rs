@@ -0,0 +1,155 @@
+use crate::data::{LoadingError, ParsingError};
+use crate::file::MAX_CELLS;
+use std::f64::consts::PI;
+use std::path::Path;
+
+/// A puzzle played on the nodes of a graph: a click on a node changes its state and the state of
+/// the nodes its edges lead to.
+///
+/// Graphs are read from text files with one statement per line, `#` starting a comment:
+///
+/// ```text
+/// nodes 4            # number of nodes, numbered from 0
+/// directed           # optional, edges are undirected otherwise
+/// edge 0 1           # a click on node 0 affects node 1 (and vice versa if undirected)
+/// position 0 0.5 0   # optional position of a node, used to draw it
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Graph {
+    pub positions: Vec<(f64, f64)>,
+    pub edges: Vec<(usize, usize)>,
+    pub directed: bool,
+    /// File the graph was read from, empty if it was built otherwise
+    pub path: String,
+    /// Nodes affected by a click on each node, besides the node itself
+    neighbours: Vec<Vec<usize>>,
+}
+
+impl Graph {
+    /// A graph whose nodes are laid out on a circle.
+    pub fn new(nodes: usize, edges: Vec<(usize, usize)>, directed: bool) -> Self {
+        let positions = (0..nodes)
+            .map(|node| {
+                let angle = 2.0 * PI * node as f64 / nodes as f64 - PI / 2.0;
+                (angle.cos(), angle.sin())
+            })
+            .collect();
+
+        let mut neighbours = vec![vec![]; nodes];
+        for &(from, to) in &edges {
+            neighbours[from].push(to);
+            if !directed {
+                neighbours[to].push(from);
+            }
+        }
+
+        Self {
+            positions,
+            edges,
+            directed,
+            path: String::new(),
+            neighbours,
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LoadingError> {
+        let path = path.as_ref();
+        let mut graph: Graph = std::fs::read_to_string(path)?.parse()?;
+        graph.path = path.to_string_lossy().into_owned();
+        Ok(graph)
+    }
+
+    pub fn nodes(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn neighbours(&self, node: usize) -> &[usize] {
+        &self.neighbours[node]
+    }
+}
+
+impl std::str::FromStr for Graph {
+    type Err = LoadingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut nodes = None;
+        let mut directed = false;
+        let mut edges = vec![];
+        let mut positions = vec![];
+
+        for line in s.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            match tokens.as_slice() {
+                [] => {}
+                ["nodes", n] => nodes = Some(n.parse::<usize>().map_err(|_| ParsingError)?),
+                ["directed"] => directed = true,
+                ["edge", from, to] => edges.push((
+                    from.parse::<usize>().map_err(|_| ParsingError)?,
+                    to.parse::<usize>().map_err(|_| ParsingError)?,
+                )),
+                ["position", node, x, y] => positions.push((
+                    node.parse::<usize>().map_err(|_| ParsingError)?,
+                    x.parse::<f64>().map_err(|_| ParsingError)?,
+                    y.parse::<f64>().map_err(|_| ParsingError)?,
+                )),
+                _ => return Err(ParsingError.into()),
+            }
+        }
+
+        let nodes = nodes.ok_or(ParsingError)?;
+        // Like grids, graphs are bounded before allocating their nodes
+        if nodes > MAX_CELLS {
+            return Err(LoadingError::Invalid(format!(
+                "the graph has more than {} nodes",
+                MAX_CELLS
+            )));
+        }
+        let in_range = |node: usize| node < nodes;
+        if nodes == 0
+            || !edges
+                .iter()
+                .all(|&(from, to)| in_range(from) && in_range(to))
+            || !positions.iter().all(|&(node, _, _)| in_range(node))
+        {
+            return Err(ParsingError.into());
+        }
+
+        let mut graph = Graph::new(nodes, edges, directed);
+        for (node, x, y) in positions {
+            graph.positions[node] = (x, y);
+        }
+        Ok(graph)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_graph() {
+        let graph: Graph = "nodes 3 # a path\ndirected\nedge 0 1\nedge 1 2\nposition 2 1 0.5"
+            .parse()
+            .unwrap();
+        assert_eq!(graph.nodes(), 3);
+        assert_eq!(graph.neighbours(0), &[1]);
+        assert!(graph.neighbours(2).is_empty());
+        assert_eq!(graph.positions[2], (1.0, 0.5));
+    }
+
+    #[test]
+    fn rejects_invalid_graphs() {
+        for text in [
+            "nodes 18446744073709551615",
+            "nodes 1048577",
+            "nodes 0",
+            "nodes 2\nedge 0 2",
+            "nodes 2\nedge 2 0",
+            "nodes 2\nposition 2 0 0",
+            "edge 0 1",
+        ] {
+            assert!(text.parse::<Graph>().is_err(), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,13 @@
+//! Solvers for generalized *Lights Out* puzzles.
+//!
+//! A puzzle is described by the [`data::Params`] of a [`data::SolverState`] and solved by any of
+//! the engines in [`solver`], all implementing the [`solver::Solver`] trait.
+pub mod benchmark;
+pub mod data;
+pub mod file;
+pub mod generator;
+pub mod graph;
+pub mod neighbourhood;
+pub mod notation;
+pub mod settings;
+pub mod solver;
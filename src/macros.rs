@@ -1,9 +1,23 @@
+/// Lens from the `ParamsState` of the window to the parameter `$field` of its puzzle.
+#[macro_export]
+macro_rules! param {
+    ($field: ident) => {
+        druid::LensExt::then(
+            $crate::state::ParamsState::state,
+            druid::LensExt::then(
+                druid::lens!(lights_out::data::SolverState, params),
+                druid::lens!(lights_out::data::Params, $field),
+            ),
+        )
+    };
+}
+
 #[macro_export]
 macro_rules! usize_textbox {
     ($field: ident) => {
         ValueTextBox::new(TextBox::new(), ParseFormatter::new())
             .validate_while_editing(false)
-            .lens(Params::$field)
+            .lens($crate::param!($field))
             .controller(ParamsController {})
             .align_left()
     };
@@ -14,7 +28,7 @@ macro_rules! nonzero_textbox {
     ($field: ident) => {
         ValueTextBox::new(TextBox::new(), NonZeroFormatter)
             .validate_while_editing(false)
-            .lens(Params::$field)
+            .lens($crate::param!($field))
             .controller(ParamsController {})
             .align_left()
     };
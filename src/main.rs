@@ -1,39 +1,75 @@
-#![windows_subsystem = "windows"]
+#![cfg_attr(feature = "gui", windows_subsystem = "windows")]
+mod cli;
+#[cfg(feature = "gui")]
 mod controllers;
-mod data;
+#[cfg(feature = "gui")]
 mod formatters;
+#[cfg(feature = "gui")]
 mod macros;
-mod settings;
-mod solver;
+#[cfg(feature = "gui")]
+mod state;
+#[cfg(feature = "gui")]
 mod view;
+#[cfg(feature = "gui")]
 mod widgets;
 
-use data::SolverState;
+#[cfg(feature = "gui")]
 use druid::{AppLauncher, LocalizedString, Size, WindowDesc};
+#[cfg(feature = "gui")]
 use lazy_static::lazy_static;
-use settings::Settings;
-use view::build_ui;
+#[cfg(feature = "gui")]
+use lights_out::settings::Settings;
+#[cfg(feature = "gui")]
+use state::AppState;
+#[cfg(feature = "gui")]
+use view::{build_menu, build_ui};
 
+#[cfg(feature = "gui")]
 lazy_static! {
     static ref SETTINGS: Settings = Settings::new().unwrap();
 }
 
 pub fn main() {
+    // Any argument selects the command line interface, which never opens a window
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    #[cfg(feature = "gui")]
+    if args.is_empty() {
+        launch_gui();
+        return;
+    }
+    #[cfg(all(windows, feature = "gui"))]
+    attach_console();
+    std::process::exit(cli::run(&args));
+}
+
+/// Writes the output of the command line interface to the console it was run from, which the
+/// Windows executable of the GUI does not get by itself.
+#[cfg(all(windows, feature = "gui"))]
+fn attach_console() {
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    // Without a parent console, e.g. when started from Explorer, the output is lost as before
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(feature = "gui")]
+fn launch_gui() {
     let window = WindowDesc::new(build_ui)
+        .menu(build_menu())
         .title(
             LocalizedString::new("lights-out-window-title").with_placeholder("Lights Out Solver"),
         )
-        .window_size(Size::new(760.0, 615.0))
+        .window_size(Size::new(1300.0, 650.0))
         .with_min_size(Size::new(420.0, 400.0));
 
-    let solver_state = SolverState::new(
-        SETTINGS.rows,
-        SETTINGS.columns,
-        SETTINGS.states,
-        SETTINGS.objective,
-    );
+    let app_state = AppState::from_settings(&SETTINGS);
 
     AppLauncher::with_window(window)
-        .launch(solver_state)
+        .launch(app_state)
         .expect("launch failed");
 }
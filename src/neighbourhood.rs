@@ -0,0 +1,246 @@
+use crate::data::{Grid, GridCoord};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use strum::{Display, EnumString};
+
+/// Shape of the set of cells affected by a click.
+#[derive(EnumString, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Neighbourhood {
+    /// The clicked cell and the cells up to `radius` steps away along its row and column
+    #[default]
+    Plus,
+    /// The clicked cell and the cells up to `radius` steps away along its diagonals
+    XShape,
+    /// The square of side `2 * radius + 1` centred on the clicked cell
+    Moore,
+    /// The clicked cell and the cells a knight's move away from it
+    Knight,
+    /// The cells drawn on a mask of side `2 * radius + 1` centred on the clicked cell
+    Custom,
+}
+
+impl Neighbourhood {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Neighbourhood::Plus => "Plus",
+            Neighbourhood::XShape => "X shape",
+            Neighbourhood::Moore => "Moore",
+            Neighbourhood::Knight => "Knight",
+            Neighbourhood::Custom => "Custom",
+        }
+    }
+}
+
+/// Shape of the cells of a grid.
+#[derive(
+    EnumString, Display, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Tiling {
+    #[default]
+    Square,
+    /// Pointy-topped hexagons, with odd rows shifted right by half a cell
+    Hex,
+    /// Triangles alternately pointing up and down, the top-left one pointing up
+    Triangle,
+}
+
+impl Tiling {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tiling::Square => "Square",
+            Tiling::Hex => "Hex",
+            Tiling::Triangle => "Triangle",
+        }
+    }
+
+    /// Number of classes of cells sharing the same neighbour offsets.
+    pub fn classes(&self) -> usize {
+        match self {
+            Tiling::Square => 1,
+            Tiling::Hex | Tiling::Triangle => 2,
+        }
+    }
+
+    /// Class of the cell in `coord`: the parity of its row for hexagons and whether it points
+    /// down for triangles.
+    pub fn class(&self, coord: GridCoord) -> usize {
+        match self {
+            Tiling::Square => 0,
+            Tiling::Hex => coord.row % 2,
+            Tiling::Triangle => (coord.row + coord.col) % 2,
+        }
+    }
+
+    /// Offsets of a cell of class `class` and of the cells sharing an edge with it, weighted by
+    /// `weights`. Square cells use the configured stencil instead.
+    pub fn edge_offsets(&self, class: usize, weights: Weights) -> Vec<Offset> {
+        let offsets: &[(isize, isize)] = match (self, class) {
+            (Tiling::Square, _) => &[(0, 0), (-1, 0), (1, 0), (0, -1), (0, 1)],
+            (Tiling::Hex, 0) => &[(0, 0), (0, -1), (0, 1), (-1, -1), (-1, 0), (1, -1), (1, 0)],
+            (Tiling::Hex, _) => &[(0, 0), (0, -1), (0, 1), (-1, 0), (-1, 1), (1, 0), (1, 1)],
+            (Tiling::Triangle, 0) => &[(0, 0), (0, -1), (0, 1), (1, 0)],
+            (Tiling::Triangle, _) => &[(0, 0), (0, -1), (0, 1), (-1, 0)],
+        };
+        offsets
+            .iter()
+            .map(|&(row, col)| Offset {
+                row,
+                col,
+                weight: weights.of(row, col),
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Offset {
+    pub row: isize,
+    pub col: isize,
+    /// Increment applied to the cell for each click
+    pub weight: usize,
+}
+
+/// Increments applied by a click to the clicked cell and to the other affected cells.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Weights {
+    pub centre: usize,
+    pub neighbours: usize,
+}
+
+impl Weights {
+    pub fn of(&self, row: isize, col: isize) -> usize {
+        if row == 0 && col == 0 {
+            self.centre
+        } else {
+            self.neighbours
+        }
+    }
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            centre: 1,
+            neighbours: 1,
+        }
+    }
+}
+
+/// Offsets, relative to the clicked cell, of the cells affected by a click.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stencil {
+    offsets: Arc<Vec<Offset>>,
+    weights: Weights,
+}
+
+impl Stencil {
+    pub fn new(neighbourhood: Neighbourhood, radius: usize, mask: &Grid, weights: Weights) -> Self {
+        let r = radius as isize;
+        match neighbourhood {
+            Neighbourhood::Plus => {
+                Self::from_predicate(r, weights, |row, col| row == 0 || col == 0)
+            }
+            Neighbourhood::XShape => {
+                Self::from_predicate(r, weights, |row, col| row.abs() == col.abs())
+            }
+            Neighbourhood::Moore => Self::from_predicate(r, weights, |_, _| true),
+            Neighbourhood::Knight => Self::from_predicate(2, weights, |row, col| {
+                (row == 0 && col == 0) || row.abs() * col.abs() == 2
+            }),
+            Neighbourhood::Custom => Self::from_mask(mask, weights),
+        }
+    }
+
+    /// The stencil made of the offsets of the non-zero enabled cells of `mask`, whose central cell
+    /// is the clicked one, each weighted by the state of its cell. `weights` is only used by the
+    /// tilings other than the square one.
+    pub fn from_mask(mask: &Grid, weights: Weights) -> Self {
+        let center_row = (mask.rows / 2) as isize;
+        let center_col = (mask.columns / 2) as isize;
+        let mut offsets = vec![];
+        for row in 0..mask.rows {
+            for col in 0..mask.columns {
+                let cell = &mask[GridCoord { row, col }];
+                if !cell.disabled && cell.state != 0 {
+                    offsets.push(Offset {
+                        row: row as isize - center_row,
+                        col: col as isize - center_col,
+                        weight: cell.state,
+                    });
+                }
+            }
+        }
+        Self {
+            offsets: Arc::new(offsets),
+            weights,
+        }
+    }
+
+    /// Draws the stencil on a square mask of side `2 * radius + 1` whose cells have `states`
+    /// states, dropping the offsets that do not fit.
+    pub fn to_mask(&self, radius: usize, states: usize) -> Grid {
+        let side = 2 * radius + 1;
+        let mut mask = Grid::new(side, side, states);
+        for offset in self.offsets() {
+            let row = offset.row + radius as isize;
+            let col = offset.col + radius as isize;
+            if (0..side as isize).contains(&row) && (0..side as isize).contains(&col) {
+                mask[GridCoord {
+                    row: row as usize,
+                    col: col as usize,
+                }]
+                .state = offset.weight % states;
+            }
+        }
+        mask
+    }
+
+    fn from_predicate(
+        radius: isize,
+        weights: Weights,
+        predicate: impl Fn(isize, isize) -> bool,
+    ) -> Self {
+        let mut offsets = vec![];
+        for row in -radius..=radius {
+            for col in -radius..=radius {
+                if predicate(row, col) {
+                    offsets.push(Offset {
+                        row,
+                        col,
+                        weight: weights.of(row, col),
+                    });
+                }
+            }
+        }
+        Self {
+            offsets: Arc::new(offsets),
+            weights,
+        }
+    }
+
+    pub fn offsets(&self) -> &[Offset] {
+        &self.offsets
+    }
+
+    pub fn weights(&self) -> Weights {
+        self.weights
+    }
+
+    /// Whether the stencil is the classic one, made of the clicked cell and its four orthogonal
+    /// neighbours, each incremented by one.
+    pub fn is_von_neumann(&self) -> bool {
+        let von_neumann = Stencil::default();
+        self.offsets.len() == von_neumann.offsets.len()
+            && von_neumann.offsets.iter().all(|o| self.offsets.contains(o))
+    }
+}
+
+impl Default for Stencil {
+    fn default() -> Self {
+        Self::from_predicate(1, Weights::default(), |row, col| row == 0 || col == 0)
+    }
+}
@@ -0,0 +1,449 @@
+//! Notations used to exchange puzzles as strings, besides the puzzle files:
+//!
+//! * `text`: the cell states separated by whitespace, one row per line, as written by
+//!   [`Grid::to_text`];
+//! * `ascii`: one character per cell (`0`-`9`, then `a`-`z` for states from 10 to 35), rows
+//!   separated by `/` or new lines, e.g. `01101/10001/01110`;
+//! * `rle`: the run-length encoding used for cellular automata, e.g. `x = 3, y = 2` followed by
+//!   `b2o$3o!`. States are `b` and `o` on two-state puzzles, `.` and `A`-`X` otherwise, `$` ends a
+//!   row and `!` the pattern, each symbol being optionally preceded by its count;
+//! * `asp`: the facts of the *clingo* program, `dim(Rows,Columns).` followed by
+//!   `cell(Row,Column,State).` for the cells in a state other than 0, counted from 1.
+//!
+//! In every notation `-` (or `disabled(Row,Column).`) marks a disabled cell.
+use crate::data::{Grid, GridCoord};
+use crate::file::{parse_cells, FileError, MAX_CELLS};
+use regex::Regex;
+use strum::{Display, EnumIter, EnumString};
+
+/// Cell states listed row by row, `None` standing for a disabled cell.
+pub type Cells = Vec<Vec<Option<usize>>>;
+
+#[derive(EnumString, EnumIter, Display, Clone, Copy, Debug, PartialEq, Eq)]
+#[strum(serialize_all = "snake_case")]
+pub enum Notation {
+    Text,
+    Ascii,
+    Rle,
+    Asp,
+}
+
+impl Notation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Notation::Text => "Text",
+            Notation::Ascii => "ASCII",
+            Notation::Rle => "RLE",
+            Notation::Asp => "ASP facts",
+        }
+    }
+
+    /// Guesses the notation `text` is written in.
+    pub fn detect(text: &str) -> Notation {
+        let text = text.trim();
+        if ["dim(", "cell(", "disabled("]
+            .iter()
+            .any(|fact| text.contains(fact))
+        {
+            Notation::Asp
+        } else if text.starts_with('x') || text.contains(['$', '!']) {
+            Notation::Rle
+        } else if text.lines().any(|line| {
+            line.split('#')
+                .next()
+                .unwrap_or_default()
+                .split_whitespace()
+                .count()
+                > 1
+        }) {
+            Notation::Text
+        } else {
+            Notation::Ascii
+        }
+    }
+
+    pub fn read(&self, text: &str) -> Result<Cells, FileError> {
+        match self {
+            Notation::Text => parse_cells(text),
+            Notation::Ascii => read_ascii(text),
+            Notation::Rle => read_rle(text),
+            Notation::Asp => read_asp(text),
+        }
+    }
+
+    /// Writes the states of `grid`, failing if the notation has no symbol for some of them.
+    pub fn write(&self, grid: &Grid) -> Result<String, FileError> {
+        match self {
+            Notation::Text => Ok(grid.to_text()),
+            Notation::Ascii => write_ascii(grid),
+            Notation::Rle => write_rle(grid),
+            Notation::Asp => Ok(write_asp(grid)),
+        }
+    }
+}
+
+fn read_ascii(text: &str) -> Result<Cells, FileError> {
+    let cells: Cells = text
+        .split(['/', '\n'])
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .enumerate()
+        .map(|(row, line)| {
+            line.chars()
+                .map(|symbol| match symbol {
+                    '-' => Ok(None),
+                    symbol => symbol
+                        .to_digit(36)
+                        .map(|state| Some(state as usize))
+                        .ok_or_else(|| {
+                            FileError::Invalid(format!(
+                                "row {}: invalid cell `{}`",
+                                row + 1,
+                                symbol
+                            ))
+                        }),
+                })
+                .collect()
+        })
+        .collect::<Result<_, _>>()?;
+    check_rows(cells)
+}
+
+fn write_ascii(grid: &Grid) -> Result<String, FileError> {
+    let rows = (0..grid.rows)
+        .map(|row| {
+            (0..grid.columns)
+                .map(|col| {
+                    let cell = &grid[GridCoord { row, col }];
+                    if cell.disabled {
+                        Ok('-')
+                    } else {
+                        char::from_digit(cell.state as u32, 36)
+                            .ok_or_else(|| unwritable(cell.state))
+                    }
+                })
+                .collect::<Result<String, _>>()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows.join("/"))
+}
+
+fn read_rle(text: &str) -> Result<Cells, FileError> {
+    let mut size = None;
+    let mut rows = vec![vec![]];
+    let mut count: Option<usize> = None;
+    // Cells and rows listed so far, bounding the memory taken by long runs
+    let mut listed = 0;
+    let lines = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'));
+    'lines: for line in lines {
+        if line.starts_with('x') {
+            size = Some(read_rle_header(line)?);
+            continue;
+        }
+        // Runs cannot go beyond the size given in the header
+        let (width, height) = size.unwrap_or((MAX_CELLS, MAX_CELLS));
+        for symbol in line.chars() {
+            let state = match symbol {
+                '0'..='9' => {
+                    let digit = symbol.to_digit(10).unwrap_or_default() as usize;
+                    let run = count
+                        .unwrap_or(0)
+                        .checked_mul(10)
+                        .and_then(|run| run.checked_add(digit))
+                        .filter(|&run| run <= MAX_CELLS)
+                        .ok_or_else(|| too_long(rows.len()))?;
+                    count = Some(run);
+                    continue;
+                }
+                '$' => {
+                    let run = count.take().unwrap_or(1);
+                    listed += run;
+                    if run > height || listed > MAX_CELLS {
+                        return Err(too_long(rows.len()));
+                    }
+                    rows.extend((0..run).map(|_| vec![]));
+                    continue;
+                }
+                '!' => break 'lines,
+                symbol if symbol.is_whitespace() => continue,
+                'b' | '.' => Some(0),
+                'o' => Some(1),
+                'A'..='X' => Some(symbol as usize - 'A' as usize + 1),
+                '-' => None,
+                symbol => {
+                    return Err(FileError::Invalid(format!(
+                        "row {}: invalid cell `{}`",
+                        rows.len(),
+                        symbol
+                    )))
+                }
+            };
+            let run = count.take().unwrap_or(1);
+            listed += run;
+            if run > width || listed > MAX_CELLS {
+                return Err(too_long(rows.len()));
+            }
+            let row = rows.last_mut().unwrap();
+            row.extend((0..run).map(|_| state));
+        }
+    }
+
+    // Dead cells at the end of a row and rows at the bottom of the pattern can be left out
+    if rows.last().is_some_and(Vec::is_empty) && rows.len() > 1 {
+        rows.pop();
+    }
+    let (columns, height) = size.unwrap_or_default();
+    let columns = rows.iter().map(Vec::len).fold(columns, usize::max);
+    let height = height.max(rows.len());
+    check_size(height, columns)?;
+    rows.resize(height, vec![]);
+    for row in &mut rows {
+        row.resize(columns, Some(0));
+    }
+    check_rows(rows)
+}
+
+fn too_long(row: usize) -> FileError {
+    FileError::Invalid(format!(
+        "row {}: run too long for the size of the grid",
+        row
+    ))
+}
+
+/// Reads the size of the pattern from a header like `x = 3, y = 2, rule = B3/S23`.
+fn read_rle_header(line: &str) -> Result<(usize, usize), FileError> {
+    let mut x = None;
+    let mut y = None;
+    for item in line.split(',') {
+        let (key, value) = item.split_once('=').unwrap_or((item, ""));
+        let value = value.trim().parse().ok();
+        match key.trim() {
+            "x" => x = value,
+            "y" => y = value,
+            _ => {}
+        }
+    }
+    x.zip(y)
+        .ok_or_else(|| FileError::Invalid(format!("invalid header `{}`", line)))
+}
+
+fn write_rle(grid: &Grid) -> Result<String, FileError> {
+    let symbol = |state: usize| match (grid.states, state) {
+        (2, 0) => Ok('b'),
+        (2, 1) => Ok('o'),
+        (_, 0) => Ok('.'),
+        (_, 1..=24) => Ok((b'A' + state as u8 - 1) as char),
+        _ => Err(unwritable(state)),
+    };
+
+    let mut text = format!("x = {}, y = {}\n", grid.columns, grid.rows);
+    // Row the next symbols go to
+    let mut current = 0;
+    for row in 0..grid.rows {
+        let mut runs: Vec<(usize, char)> = vec![];
+        for col in 0..grid.columns {
+            let cell = &grid[GridCoord { row, col }];
+            let symbol = if cell.disabled {
+                '-'
+            } else {
+                symbol(cell.state)?
+            };
+            match runs.last_mut() {
+                Some((count, last)) if *last == symbol => *count += 1,
+                _ => runs.push((1, symbol)),
+            }
+        }
+        if runs
+            .last()
+            .is_some_and(|&(_, symbol)| symbol == 'b' || symbol == '.')
+        {
+            runs.pop();
+        }
+        if runs.is_empty() {
+            continue;
+        }
+        if row > current {
+            text += &run(row - current, '$');
+            current = row;
+        }
+        for (count, symbol) in runs {
+            text += &run(count, symbol);
+        }
+    }
+    text.push('!');
+    Ok(text)
+}
+
+fn run(count: usize, symbol: char) -> String {
+    match count {
+        1 => symbol.to_string(),
+        count => format!("{}{}", count, symbol),
+    }
+}
+
+fn read_asp(text: &str) -> Result<Cells, FileError> {
+    let fact = Regex::new(r"(?P<name>[a-z_]\w*)\((?P<args>[^)]*)\)\s*\.").unwrap();
+    let mut size = None;
+    let mut states = vec![];
+    let mut disabled = vec![];
+    for c in fact.captures_iter(text) {
+        // Other facts describe the rules of the puzzle rather than its cells, and their arguments
+        // need not be numbers
+        if !matches!(&c["name"], "dim" | "cell" | "disabled") {
+            continue;
+        }
+        let invalid = || FileError::Invalid(format!("invalid fact `{}`", &c[0]));
+        let args: Vec<usize> = c["args"]
+            .split(',')
+            .map(|arg| arg.trim().parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid())?;
+        match (&c["name"], args.as_slice()) {
+            ("dim", &[rows, columns]) => size = Some((rows, columns)),
+            ("cell", &[row, col, state]) if row > 0 && col > 0 => {
+                states.push((row - 1, col - 1, state))
+            }
+            ("disabled", &[row, col]) if row > 0 && col > 0 => disabled.push((row - 1, col - 1)),
+            _ => return Err(invalid()),
+        }
+    }
+
+    let (rows, columns) = size.unwrap_or_else(|| {
+        states
+            .iter()
+            .map(|&(row, col, _)| (row, col))
+            .chain(disabled.iter().copied())
+            .fold((0, 0), |(rows, columns), (row, col)| {
+                (rows.max(row + 1), columns.max(col + 1))
+            })
+    });
+    check_size(rows, columns)?;
+    let mut cells = vec![vec![Some(0); columns]; rows];
+    let outside = |row: usize, col: usize| {
+        FileError::Invalid(format!(
+            "cell ({}, {}) outside of a {}x{} grid",
+            row + 1,
+            col + 1,
+            rows,
+            columns
+        ))
+    };
+    for (row, col, state) in states {
+        *cells
+            .get_mut(row)
+            .and_then(|cells| cells.get_mut(col))
+            .ok_or_else(|| outside(row, col))? = Some(state);
+    }
+    for (row, col) in disabled {
+        *cells
+            .get_mut(row)
+            .and_then(|cells| cells.get_mut(col))
+            .ok_or_else(|| outside(row, col))? = None;
+    }
+    check_rows(cells)
+}
+
+fn write_asp(grid: &Grid) -> String {
+    let mut text = format!("dim({},{}).\n", grid.rows, grid.columns);
+    for row in 0..grid.rows {
+        for col in 0..grid.columns {
+            let cell = &grid[GridCoord { row, col }];
+            let (i, j) = (row + 1, col + 1);
+            if cell.disabled {
+                text += &format!("disabled({i},{j}).\n");
+            } else if cell.state != 0 {
+                text += &format!("cell({i},{j},{}).\n", cell.state);
+            }
+        }
+    }
+    text
+}
+
+fn check_rows(cells: Cells) -> Result<Cells, FileError> {
+    match cells.first() {
+        Some(first) if !first.is_empty() => {
+            match cells.iter().position(|row| row.len() != first.len()) {
+                Some(row) => Err(FileError::Invalid(format!(
+                    "row {} has {} cells instead of {}",
+                    row + 1,
+                    cells[row].len(),
+                    first.len()
+                ))),
+                None => Ok(cells),
+            }
+        }
+        _ => Err(FileError::Invalid(String::from("no cells"))),
+    }
+}
+
+/// Fails on grids too big to be read, which could not be allocated.
+fn check_size(rows: usize, columns: usize) -> Result<(), FileError> {
+    match rows.checked_mul(columns) {
+        Some(cells) if cells <= MAX_CELLS => Ok(()),
+        _ => Err(FileError::Invalid(format!(
+            "a {}x{} grid has more than {} cells",
+            rows, columns, MAX_CELLS
+        ))),
+    }
+}
+
+fn unwritable(state: usize) -> FileError {
+    FileError::Invalid(format!(
+        "state {} cannot be written in this notation",
+        state
+    ))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::neighbourhood::Tiling;
+    use crate::solver::tests::random_puzzle;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+    use strum::IntoEnumIterator;
+
+    /// The cells of `grid` as the notations read them.
+    pub(crate) fn cells(grid: &Grid) -> Cells {
+        (0..grid.rows)
+            .map(|row| {
+                (0..grid.columns)
+                    .map(|col| {
+                        let cell = &grid[GridCoord { row, col }];
+                        (!cell.disabled).then_some(cell.state)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reads_what_it_writes() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        for notation in Notation::iter() {
+            for states in [2, 3, 5] {
+                for tiling in [Tiling::Square, Tiling::Hex, Tiling::Triangle] {
+                    let (rows, columns) = (rng.gen_range(1..=6), rng.gen_range(1..=6));
+                    let mut grid = random_puzzle(rows, columns, states, &mut rng).grid;
+                    grid.tiling = tiling;
+                    grid[GridCoord { row: 0, col: 0 }].disabled = true;
+                    let text = notation.write(&grid).unwrap();
+                    assert_eq!(notation.read(&text).unwrap(), cells(&grid), "{}", text);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn skips_rule_facts() {
+        let text = "dim(1,3).tiling(square).offset(0,-1,0,1).wrap(rows).cell(1,2,1).disabled(1,3).";
+        assert_eq!(
+            Notation::Asp.read(text).unwrap(),
+            vec![vec![Some(0), Some(1), None]]
+        );
+        assert!(Notation::Asp.read("dim(1,3).cell(1,x,1).").is_err());
+    }
+}
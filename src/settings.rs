@@ -1,26 +1,91 @@
+use crate::data::Topology;
+use crate::neighbourhood::{Neighbourhood, Tiling};
+use crate::solver::Limits;
 use config::{Config, ConfigError};
-use druid::Data;
 use std::str::FromStr;
-use strum::{EnumIter, EnumString};
+use std::time::Duration;
+use strum::{Display, EnumIter, EnumString};
 
-#[derive(EnumString, EnumIter, Clone, Debug, Data)]
+#[derive(EnumString, EnumIter, Display, Clone, Debug)]
 #[strum(serialize_all = "snake_case")]
 pub enum Solver {
     Clingo { clingo_path: String },
     Internal,
     InternalPar { threads: usize },
+    Linear,
+    Bitset,
 }
 
+#[derive(EnumString, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[strum(serialize_all = "snake_case")]
+pub enum Optimize {
+    /// Keep the first solution found
+    #[default]
+    None,
+    /// Minimize the total number of clicks
+    Clicks,
+    /// Minimize the number of cells clicked at least once
+    PressedCells,
+}
+
+impl Optimize {
+    pub fn cost(&self, clicks: &[usize]) -> usize {
+        match self {
+            Optimize::None => 0,
+            Optimize::Clicks => clicks.iter().sum(),
+            Optimize::PressedCells => clicks.iter().filter(|&&c| c > 0).count(),
+        }
+    }
+
+    pub fn description(&self) -> Option<&'static str> {
+        match self {
+            Optimize::None => None,
+            Optimize::Clicks => Some("min clicks"),
+            Optimize::PressedCells => Some("min pressed cells"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Settings {
     pub solver: Solver,
+    pub optimize: Optimize,
+    pub limits: Limits,
     pub rows: usize,
     pub columns: usize,
     pub states: usize,
     pub objective: usize,
+    pub topology: Topology,
+    pub tiling: Tiling,
+    pub neighbourhood: Neighbourhood,
+    pub radius: usize,
+    pub centre_weight: usize,
+    pub neighbour_weight: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            solver: Solver::InternalPar { threads: 0 },
+            optimize: Optimize::None,
+            limits: Limits::default(),
+            rows: 3,
+            columns: 3,
+            states: 2,
+            objective: 1,
+            topology: Topology::Bounded,
+            tiling: Tiling::Square,
+            neighbourhood: Neighbourhood::Plus,
+            radius: 1,
+            centre_weight: 1,
+            neighbour_weight: 1,
+        }
+    }
 }
 
 impl Settings {
     pub fn new() -> Result<Self, ConfigError> {
+        let default = Self::default();
         let settings = Config::builder()
             .add_source(config::File::with_name("settings.toml"))
             .build()?;
@@ -33,24 +98,75 @@ impl Settings {
                 let clingo_path = settings.get_string("clingo_path")?;
                 Solver::Clingo { clingo_path }
             }
-            Solver::Internal => solver,
+            Solver::Internal | Solver::Linear | Solver::Bitset => solver,
             Solver::InternalPar { threads: _ } => {
                 let threads = settings.get_int("threads")?.try_into().unwrap_or(0usize);
                 Solver::InternalPar { threads }
             }
         };
 
-        let rows = settings.get("default.rows").unwrap_or(3);
-        let columns = settings.get("default.columns").unwrap_or(3);
-        let states = settings.get("default.states").unwrap_or(2);
-        let objective = settings.get("default.objective").unwrap_or(1);
+        let optimize = match settings.get_string("optimize") {
+            Ok(optimize) => Optimize::from_str(&optimize)
+                .map_err(|_| ConfigError::Message(String::from("Invalid optimize value")))?,
+            Err(_) => default.optimize,
+        };
+
+        // Non-positive values leave the solver unbounded
+        let time = settings
+            .get_float("time_limit")
+            .ok()
+            .filter(|&seconds| seconds > 0.0)
+            .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok());
+        let iterations = settings
+            .get_int("max_iterations")
+            .ok()
+            .and_then(|iterations| u64::try_from(iterations).ok())
+            .filter(|&iterations| iterations > 0);
+        let limits = Limits { time, iterations };
+
+        let rows = settings.get("default.rows").unwrap_or(default.rows);
+        let columns = settings.get("default.columns").unwrap_or(default.columns);
+        let states = settings.get("default.states").unwrap_or(default.states);
+        let objective = settings
+            .get("default.objective")
+            .unwrap_or(default.objective);
+        let topology = match settings.get_string("default.topology") {
+            Ok(topology) => Topology::from_str(&topology)
+                .map_err(|_| ConfigError::Message(String::from("Invalid topology")))?,
+            Err(_) => default.topology,
+        };
+        let tiling = match settings.get_string("default.tiling") {
+            Ok(tiling) => Tiling::from_str(&tiling)
+                .map_err(|_| ConfigError::Message(String::from("Invalid tiling")))?,
+            Err(_) => default.tiling,
+        };
+        let neighbourhood = match settings.get_string("default.neighbourhood") {
+            Ok(neighbourhood) => Neighbourhood::from_str(&neighbourhood)
+                .map_err(|_| ConfigError::Message(String::from("Invalid neighbourhood")))?,
+            Err(_) => default.neighbourhood,
+        };
+        let radius = settings.get("default.radius").unwrap_or(default.radius);
+        let centre_weight = settings
+            .get("default.centre_weight")
+            .unwrap_or(default.centre_weight);
+        let neighbour_weight = settings
+            .get("default.neighbour_weight")
+            .unwrap_or(default.neighbour_weight);
 
         Ok(Self {
             solver,
+            optimize,
+            limits,
             rows,
             columns,
             states,
             objective,
+            topology,
+            tiling,
+            neighbourhood,
+            radius,
+            centre_weight,
+            neighbour_weight,
         })
     }
 }
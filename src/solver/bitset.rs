@@ -0,0 +1,464 @@
+use super::{Control, Solver, MAX_OPTIMIZED};
+use crate::data::{
+    Grid, GridCoord, Puzzle, QuietPattern, Solution, SolutionSet, SolvingError, Stats,
+    UnsupportedError,
+};
+use crate::neighbourhood::Tiling;
+use crate::settings::Optimize;
+use std::time::Instant;
+
+/// Cells a line can hold, one bit of a word each.
+const MAX_WIDTH: usize = u128::BITS as usize;
+
+/// Solver of two-state puzzles chasing the lights a whole line at a time, each line being a word
+/// with a bit per cell: clicking a line is a handful of shifts and XORs.
+///
+/// The chase is linear in the guessed clicks, so instead of trying every guess it chases each
+/// guessed cell alone, then finds by elimination the guesses leaving no light behind. Finding the
+/// optimal solution still visits every solution, which takes time exponential in the number of
+/// quiet patterns.
+pub struct Bitset {
+    pub optimize: Optimize,
+}
+
+impl Solver for Bitset {
+    fn solve(&self, puzzle: &Puzzle, control: &Control) -> Result<Solution, SolvingError> {
+        let time = Instant::now();
+        let board = Board::new(puzzle)?;
+
+        let (clicks, stats) = match board.solutions(control)? {
+            Some((particular, kernel)) => {
+                let (clicks, stats) = self.best(particular, &kernel, control)?;
+                (Some(board.cells(&clicks)), stats)
+            }
+            None => (None, Stats::default()),
+        };
+
+        Ok(Solution::new(puzzle, clicks, stats, time.elapsed()))
+    }
+
+    fn solve_all(
+        &self,
+        puzzle: &Puzzle,
+        control: &Control,
+    ) -> Result<Option<SolutionSet>, SolvingError> {
+        let board = Board::new(puzzle)?;
+        Ok(board
+            .solutions(control)?
+            .map(|(particular, kernel)| SolutionSet {
+                particular: board.cells(&particular),
+                quiet_patterns: kernel
+                    .iter()
+                    .map(|clicks| QuietPattern {
+                        clicks: board.cells(clicks),
+                        order: 2,
+                    })
+                    .collect(),
+                modulus: 2,
+            }))
+    }
+
+    fn optimized(&self) -> Optimize {
+        self.optimize
+    }
+}
+
+impl Bitset {
+    /// Searches the solutions for the one minimizing `optimize`, visiting them in Gray code order
+    /// so that each one differs from the previous by a single quiet pattern. With two states the
+    /// clicks and the pressed cells are the same.
+    fn best(
+        &self,
+        particular: Vec<u128>,
+        kernel: &[Vec<u128>],
+        control: &Control,
+    ) -> Result<(Vec<u128>, Stats), SolvingError> {
+        let count = u32::try_from(kernel.len())
+            .ok()
+            .and_then(|dimension| 1usize.checked_shl(dimension));
+        if self.optimize == Optimize::None {
+            let stats = Stats {
+                candidates: 1,
+                solutions: count.unwrap_or(usize::MAX),
+            };
+            return Ok((particular, stats));
+        }
+        let count = count
+            .filter(|&count| count <= MAX_OPTIMIZED)
+            .ok_or(UnsupportedError {
+                solver: "Bitset",
+                feature: "optimizing puzzles with more than 2^24 solutions",
+            })?;
+
+        control.start(count as u64);
+        let cost = |clicks: &[u128]| clicks.iter().map(|c| c.count_ones()).sum::<u32>();
+        let mut clicks = particular;
+        let mut best = (cost(&clicks), clicks.clone());
+        for index in 1..count {
+            control.advance(1).map_err(|e| {
+                e.with_stats(Stats {
+                    candidates: index,
+                    solutions: count,
+                })
+            })?;
+            let pattern = &kernel[index.trailing_zeros() as usize];
+            for (line, quiet) in clicks.iter_mut().zip(pattern) {
+                *line ^= quiet;
+            }
+            let cost = cost(&clicks);
+            if cost < best.0 {
+                best = (cost, clicks.clone());
+            }
+        }
+        let stats = Stats {
+            candidates: count,
+            solutions: count,
+        };
+        Ok((best.1, stats))
+    }
+}
+
+/// A puzzle laid out along the lines the lights are chased through, rows or columns, each line
+/// holding a bit per cell.
+struct Board {
+    /// Whether the lines are the columns of the grid
+    transposed: bool,
+    width: usize,
+    /// Whether the first line is adjacent to the last one
+    wraps_lines: bool,
+    /// Whether the first cell of a line is adjacent to the last one
+    wraps_width: bool,
+    enabled: Vec<u128>,
+    /// Enabled cells whose clicks cannot be deduced from the previous line
+    seeds: Vec<u128>,
+    state: Vec<u128>,
+    target: Vec<u128>,
+}
+
+/// The clicks of a particular solution and of a basis of the quiet patterns, a word per line.
+type Solutions = (Vec<u128>, Vec<Vec<u128>>);
+
+/// Clicks on every line along with the lights they leave off target.
+#[derive(Clone)]
+struct Chase {
+    clicks: Vec<u128>,
+    residual: Vec<u128>,
+}
+
+impl Chase {
+    fn add(&mut self, other: &Chase) {
+        for (x, y) in self.clicks.iter_mut().zip(&other.clicks) {
+            *x ^= y;
+        }
+        for (x, y) in self.residual.iter_mut().zip(&other.residual) {
+            *x ^= y;
+        }
+    }
+
+    /// The first light left off target, as its line and bit.
+    fn pivot(&self) -> Option<(usize, u128)> {
+        self.residual.iter().position(|&r| r != 0).map(|line| {
+            (
+                line,
+                self.residual[line] & self.residual[line].wrapping_neg(),
+            )
+        })
+    }
+}
+
+impl Board {
+    /// Lays out the puzzle along the rows or the columns, whichever needs fewer guesses and fits
+    /// in a word.
+    fn new(puzzle: &Puzzle) -> Result<Self, UnsupportedError> {
+        let grid = &puzzle.grid;
+        check_supported(grid)?;
+        let by_row = (grid.columns <= MAX_WIDTH).then(|| Self::lay_out(puzzle, false));
+        let by_col = (grid.rows <= MAX_WIDTH).then(|| Self::lay_out(puzzle, true));
+        by_row
+            .into_iter()
+            .chain(by_col)
+            .min_by_key(Board::guesses)
+            .ok_or(UnsupportedError {
+                solver: "Bitset",
+                feature: "grids both wider and taller than 128 cells",
+            })
+    }
+
+    fn lay_out(puzzle: &Puzzle, transposed: bool) -> Self {
+        let grid = &puzzle.grid;
+        let topology = grid.topology;
+        let (lines, width, wraps_lines, wraps_width) = if transposed {
+            let wraps = (topology.wraps_columns(), topology.wraps_rows());
+            (grid.columns, grid.rows, wraps.0, wraps.1)
+        } else {
+            let wraps = (topology.wraps_rows(), topology.wraps_columns());
+            (grid.rows, grid.columns, wraps.0, wraps.1)
+        };
+        let mut board = Self {
+            transposed,
+            width,
+            // Wrapping two lines (or cells) around makes them adjacent twice, which cancels out
+            // as does a single one being adjacent to itself on both sides
+            wraps_lines,
+            wraps_width,
+            enabled: vec![0; lines],
+            seeds: vec![0; lines],
+            state: vec![0; lines],
+            target: vec![0; lines],
+        };
+        for line in 0..lines {
+            for bit in 0..width {
+                let coord = board.coord(line, bit);
+                let cell = &grid[coord];
+                if cell.disabled {
+                    continue;
+                }
+                board.enabled[line] |= 1 << bit;
+                board.state[line] |= (cell.state as u128 & 1) << bit;
+                board.target[line] |= (puzzle.target[coord].state as u128 & 1) << bit;
+            }
+        }
+        // With wrapping lines the first one is also affected by the last, so the second one has
+        // to be guessed too
+        let seed_lines = if board.wraps_lines { 2 } else { 1 };
+        for line in 0..lines {
+            board.seeds[line] = if line < seed_lines {
+                board.enabled[line]
+            } else {
+                board.enabled[line] & !board.enabled[line - 1]
+            };
+        }
+        board
+    }
+
+    fn lines(&self) -> usize {
+        self.enabled.len()
+    }
+
+    fn coord(&self, line: usize, bit: usize) -> GridCoord {
+        if self.transposed {
+            GridCoord {
+                row: bit,
+                col: line,
+            }
+        } else {
+            GridCoord {
+                row: line,
+                col: bit,
+            }
+        }
+    }
+
+    fn guesses(&self) -> u32 {
+        self.seeds.iter().map(|s| s.count_ones()).sum()
+    }
+
+    /// The clicks of each cell of the grid, in row-major order.
+    fn cells(&self, clicks: &[u128]) -> Vec<usize> {
+        let (rows, columns) = if self.transposed {
+            (self.width, self.lines())
+        } else {
+            (self.lines(), self.width)
+        };
+        (0..rows * columns)
+            .map(|i| {
+                let (row, col) = (i / columns, i % columns);
+                let (line, bit) = if self.transposed {
+                    (col, row)
+                } else {
+                    (row, col)
+                };
+                (clicks[line] >> bit & 1) as usize
+            })
+            .collect()
+    }
+
+    /// The cells of a line next to those in `bits`.
+    fn sideways(&self, bits: u128) -> u128 {
+        let full = u128::MAX
+            .checked_shr((MAX_WIDTH - self.width) as u32)
+            .unwrap_or(0);
+        let (mut left, mut right) = (bits << 1, bits >> 1);
+        if self.wraps_width {
+            left |= bits >> (self.width - 1);
+            right |= (bits & 1) << (self.width - 1);
+        }
+        (left ^ right) & full
+    }
+
+    /// Clicks the cells in `clicks` of `line`.
+    fn press(&self, state: &mut [u128], line: usize, clicks: u128) {
+        state[line] ^= (clicks ^ self.sideways(clicks)) & self.enabled[line];
+        let last = self.lines() - 1;
+        let above = match line {
+            0 if self.wraps_lines => Some(last),
+            0 => None,
+            _ => Some(line - 1),
+        };
+        let below = match line {
+            _ if line < last => Some(line + 1),
+            _ if self.wraps_lines => Some(0),
+            _ => None,
+        };
+        for other in above.into_iter().chain(below) {
+            state[other] ^= clicks & self.enabled[other];
+        }
+    }
+
+    /// Chases the lights of `state` towards `target`, clicking the seed cells in `seed_clicks`
+    /// and each other cell if the one before it is off target.
+    fn chase(&self, state: &[u128], target: &[u128], seed_clicks: &[u128]) -> Chase {
+        let mut state = state.to_vec();
+        let mut clicks = vec![0; self.lines()];
+        for line in 0..self.lines() {
+            let forced = match line {
+                0 => 0,
+                _ => (state[line - 1] ^ target[line - 1]) & self.enabled[line] & !self.seeds[line],
+            };
+            clicks[line] = forced | seed_clicks[line];
+            self.press(&mut state, line, clicks[line]);
+        }
+        let residual = (0..self.lines())
+            .map(|line| (state[line] ^ target[line]) & self.enabled[line])
+            .collect();
+        Chase { clicks, residual }
+    }
+
+    /// The clicks of a solution along with a basis of the quiet patterns, if there is a solution.
+    ///
+    /// Each seed cell clicked alone on a blank board leaves some lights behind, and the chase
+    /// of the puzzle without guesses leaves others: the seeds to click are those whose lights
+    /// cancel out the latter, and the combinations of seeds leaving no light are quiet patterns.
+    fn solutions(&self, control: &Control) -> Result<Option<Solutions>, SolvingError> {
+        let blank = vec![0; self.lines()];
+        control.start(self.guesses() as u64 + 1);
+
+        let mut pivots: Vec<(usize, u128, Chase)> = vec![];
+        let mut kernel = vec![];
+        for (line, &seeds) in self.seeds.iter().enumerate() {
+            let mut rest = seeds;
+            while rest != 0 {
+                control.advance(1)?;
+                let seed = rest & rest.wrapping_neg();
+                rest ^= seed;
+                let mut seed_clicks = blank.clone();
+                seed_clicks[line] = seed;
+                let mut chase = self.chase(&blank, &blank, &seed_clicks);
+                reduce(&mut chase, &pivots);
+                match chase.pivot() {
+                    Some((line, bit)) => pivots.push((line, bit, chase)),
+                    None => kernel.push(chase.clicks),
+                }
+            }
+        }
+
+        control.advance(1)?;
+        let mut chase = self.chase(&self.state, &self.target, &blank);
+        reduce(&mut chase, &pivots);
+        Ok(chase.pivot().is_none().then_some((chase.clicks, kernel)))
+    }
+}
+
+/// Cancels the pivot lights of `chase` adding the chases they belong to.
+fn reduce(chase: &mut Chase, pivots: &[(usize, u128, Chase)]) {
+    for (line, bit, pivot) in pivots {
+        if chase.residual[*line] & bit != 0 {
+            chase.add(pivot);
+        }
+    }
+}
+
+/// Packing cells into words needs them all to be lights that are either on or off, and the chase
+/// needs square cells affected only by their orthogonal neighbours.
+fn check_supported(grid: &Grid) -> Result<(), UnsupportedError> {
+    if grid.graph.is_some() {
+        Err(UnsupportedError {
+            solver: "Bitset",
+            feature: "graph puzzles",
+        })
+    } else if grid.states != 2 || grid.is_mixed() {
+        Err(UnsupportedError {
+            solver: "Bitset",
+            feature: "cells with other than two states",
+        })
+    } else if grid.tiling != Tiling::Square {
+        Err(UnsupportedError {
+            solver: "Bitset",
+            feature: "tilings other than the square one",
+        })
+    } else if !grid.stencil.is_von_neumann() {
+        Err(UnsupportedError {
+            solver: "Bitset",
+            feature: "neighbourhoods other than the plus of radius 1",
+        })
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::SolverState;
+    use crate::solver::{tests::random_puzzle, Internal};
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn agrees_with_internal() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        for _ in 0..200 {
+            let puzzle = random_puzzle(rng.gen_range(1..=6), rng.gen_range(1..=6), 2, &mut rng);
+            for optimize in [Optimize::Clicks, Optimize::PressedCells] {
+                let expected = Internal { optimize }
+                    .solve(&puzzle, &Control::new())
+                    .unwrap();
+                let solution = Bitset { optimize }.solve(&puzzle, &Control::new()).unwrap();
+                assert_eq!(solution.solvable, expected.solvable, "{:?}", puzzle);
+                assert_eq!(
+                    optimize.cost(&solution.clicks),
+                    optimize.cost(&expected.clicks),
+                    "{:?}",
+                    puzzle
+                );
+            }
+            let expected = Internal {
+                optimize: Optimize::None,
+            }
+            .solve_all(&puzzle, &Control::new())
+            .unwrap();
+            let solutions = Bitset {
+                optimize: Optimize::None,
+            }
+            .solve_all(&puzzle, &Control::new())
+            .unwrap();
+            assert_eq!(
+                solutions.and_then(|s| s.count()),
+                expected.and_then(|s| s.count()),
+                "{:?}",
+                puzzle
+            );
+        }
+    }
+
+    #[test]
+    fn too_many_solutions_to_optimize() {
+        // The quiet patterns of the 64x64 grid span a space of dimension 28
+        let mut data = SolverState::new(64, 64, 2, 0);
+        data.randomize_with(0);
+        let puzzle = data.params.to_puzzle();
+
+        let solution = Bitset {
+            optimize: Optimize::None,
+        }
+        .solve(&puzzle, &Control::new())
+        .unwrap();
+        assert_eq!(solution.stats.solutions, 1 << 28);
+
+        let result = Bitset {
+            optimize: Optimize::Clicks,
+        }
+        .solve(&puzzle, &Control::new());
+        assert!(matches!(result, Err(SolvingError::Unsupported(_))));
+    }
+}
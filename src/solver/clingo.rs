@@ -1,12 +1,22 @@
-use super::Solver;
-use crate::data::{GridCoord, ParsingError, SolverState, SolvingError};
+use super::{Control, Solver};
+use crate::data::{GridCoord, ParsingError, Puzzle, Solution, SolutionSet, SolvingError, Stats};
+use crate::settings::Optimize;
 use regex::Regex;
-use std::process::Command;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
 
 const CLINGO_SOLVER_PROGRAM: &str = r"
-% Valid clicks number in range [0, possible cell states - 1]
-clicks(0..States-1) :- states(States).
-coord(1..N, 1..M) :- dim(N, M).
+% Valid clicks number in range [0, least common multiple of the cell states - 1]
+clicks(0..Modulus-1) :- modulus(Modulus).
+% Cells cycle through the default number of states unless stated otherwise
+#defined cell_states/3.
+cell_modulus(X, Y, States) :- coord(X, Y), cell_states(X, Y, States).
+cell_modulus(X, Y, States) :- coord(X, Y), states(States), not cell_states(X, Y, _).
+% Disabled cells are holes in the grid
+#defined disabled/2.
+coord(X, Y) :- dim(N, M), X = 1..N, Y = 1..M, not disabled(X, Y).
 
 % Replace undefined cells with 0-state cells
 cell(X, Y, 0) :- 
@@ -15,87 +25,256 @@ cell(X, Y, 0) :-
 % Cells with same coordinates cannot have different states
 :- cell(X, Y, State1), cell(X, Y, State2), State1 != State2.
 
-% Cells with coordinates (X,Y) and (A,B) are adjacent (or the same cell)
-adjacent(X, Y, A, B) :-
-    |X - A| + |Y - B| <= 1,
+% Cells of the same class (e.g. hexagons on even rows) share the same neighbour offsets
+#defined tiling/1.
+class(X, Y, 0) :- coord(X, Y), tiling(square).
+class(X, Y, (X - 1) \ 2) :- coord(X, Y), tiling(hex).
+class(X, Y, (X + Y) \ 2) :- coord(X, Y), tiling(triangle).
+
+% Row (column) reached moving by DX (DY) from row X (column Y), wrapping around the grid if needed
+#defined wrap/1.
+#defined offset/4.
+#defined reach/1.
+shift_row(X, DX, X + DX) :- coord(X, _), offset(_, DX, _, _), not wrap(rows).
+shift_row(X, DX, (X - 1 + DX + R * N) \ N + 1) :-
+    coord(X, _), offset(_, DX, _, _), wrap(rows), dim(N, _), reach(R).
+shift_col(Y, DY, Y + DY) :- coord(_, Y), offset(_, _, DY, _), not wrap(columns).
+shift_col(Y, DY, (Y - 1 + DY + R * M) \ M + 1) :-
+    coord(_, Y), offset(_, _, DY, _), wrap(columns), dim(_, M), reach(R).
+
+% The offset (DX,DY) of weight W takes cell (X,Y) to cell (A,B)
+reaches(X, Y, A, B, DX, DY, W) :-
     coord(X, Y),
+    class(X, Y, C),
+    offset(C, DX, DY, W),
+    shift_row(X, DX, A),
+    shift_col(Y, DY, B),
     coord(A, B).
+% A click on cell (X,Y) adds W to cell (A,B), which is the cell itself or one of its neighbours,
+% summing the weights of the offsets reaching the same cell on narrow wrapped grids
+adjacent(X, Y, A, B, W) :-
+    reaches(X, Y, A, B, _, _, _),
+    W = #sum{ V, DX, DY : reaches(X, Y, A, B, DX, DY, V) }.
+% Graphs list the cells affected by each click instead
+#defined link/5.
+adjacent(X, Y, A, B, W) :- link(X, Y, A, B, W), coord(X, Y), coord(A, B).
 
 % Consider one action for each cell. Every action consist on 0 or more clicks
 1 { action(X, Y, Clicks) : clicks(Clicks) } 1 :- coord(X, Y).
-% Minimize the number of clicks to solve the puzzle
-:~ action(X, Y, Clicks). [Clicks@1, X, Y]
+% Minimize the number of clicks to solve the puzzle...
+:~ action(X, Y, Clicks), minimize(clicks). [Clicks@1, X, Y]
+% ...or the number of cells clicked at least once
+:~ action(X, Y, Clicks), Clicks > 0, minimize(pressed_cells). [1@1, X, Y]
 
-% Sum the total clicks of cells adjacent to cell (X, Y)
+% Sum the total clicks of cells adjacent to cell (X, Y), weighted by their effect on it
 sumClicks(X, Y, Sum) :-
     coord(X, Y),
-    Sum = #sum{ Clicks, A, B : adjacent(A, B, X, Y), action(A, B, Clicks) }.
+    Sum = #sum{ Clicks * W, A, B : adjacent(A, B, X, Y, W), action(A, B, Clicks) }.
 
 % Set resulting grid cells adding the total clicks of its adjacent cells
 res(X, Y, Res) :- 
     cell(X, Y, Curr),
     Res = (Curr + Sum) \ States,    % modulo operation to wrap the result
-    states(States),
+    cell_modulus(X, Y, States),
     sumClicks(X, Y, Sum),
     coord(X, Y), 
     clicks(Curr).
 
-% Solution condition: resulting grid cells must be set to objective state
-:- res(X, Y, Obj),
+% Cells without an explicit target must be set to 0
+target(X, Y, 0) :-
     coord(X, Y),
-    not objective(Obj).
+    0 { target(X, Y, State): clicks(State), State > 0 } 0.
+
+% Solution condition: resulting grid cells must be set to their target state
+:- res(X, Y, Res),
+    target(X, Y, Target),
+    Res != Target.
 
 #show action/3.
 ";
 
 pub struct Clingo {
     pub clingo_path: String,
+    pub optimize: Optimize,
 }
 
-impl Solver for Clingo {
-    fn solve(&self, data: &mut SolverState) -> Result<(), SolvingError> {
-        std::fs::write("lights_out.lp", CLINGO_SOLVER_PROGRAM)?;
-        std::fs::write("puzzle.lp", puzzle_to_string(data))?;
+impl Clingo {
+    /// Whether the clingo executable can be run.
+    pub fn is_available(&self) -> bool {
+        Command::new(&self.clingo_path)
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    }
 
-        let output = String::from_utf8(
-            Command::new(&self.clingo_path)
-                .args(["lights_out.lp", "puzzle.lp", "-V0", "-q1"])
-                .output()?
-                .stdout,
-        )?;
+    fn minimize(&self) -> &'static str {
+        match self.optimized() {
+            Optimize::PressedCells => "pressed_cells",
+            _ => "clicks",
+        }
+    }
 
-        std::fs::remove_file("lights_out.lp")?;
-        std::fs::remove_file("puzzle.lp")?;
+    /// Runs clingo on the puzzle, killing it if `control` gets cancelled or runs out of time. The
+    /// program is piped to its standard input, so that concurrent runs share no file.
+    fn run(
+        &self,
+        puzzle: &Puzzle,
+        args: &[&str],
+        control: &Control,
+    ) -> Result<String, SolvingError> {
+        let program = format!(
+            "{}\n{}minimize({}).\n",
+            CLINGO_SOLVER_PROGRAM,
+            puzzle_to_string(puzzle),
+            self.minimize()
+        );
+
+        let mut child = Command::new(&self.clingo_path)
+            .args(["-", "-V0"])
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        // Write the input and read the output on other threads, so that neither clingo nor the
+        // solver ever block on a full pipe
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let writer = thread::spawn(move || stdin.write_all(program.as_bytes()));
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let reader = thread::spawn(move || {
+            let mut output = Vec::new();
+            stdout.read_to_end(&mut output).map(|_| output)
+        });
+        let mut stopped = Ok(());
+        while child.try_wait()?.is_none() {
+            stopped = control.check();
+            if stopped.is_err() {
+                child.kill()?;
+                child.wait()?;
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let written = writer.join().expect("the clingo writer panicked");
+        let output = reader.join().expect("the clingo reader panicked")?;
+
+        // A killed clingo stops reading its input
+        stopped?;
+        written?;
+        Ok(String::from_utf8(output)?)
+    }
+}
+
+impl Solver for Clingo {
+    fn solve(&self, puzzle: &Puzzle, control: &Control) -> Result<Solution, SolvingError> {
+        let time = Instant::now();
+        let output = self.run(puzzle, &["-q1"], control)?;
 
         let lines: Vec<&str> = output.lines().collect();
-        if lines.len() == 3 {
-            solution_from_string(data, lines[0])?;
-            data.params.solution.error = false;
+        let clicks = if lines.len() == 3 {
+            Some(clicks_from_string(puzzle, lines[0])?)
         } else {
-            data.params.solution.error = true;
-        }
+            None
+        };
+
+        // Clingo does not report how much of the search space it explored
+        Ok(Solution::new(
+            puzzle,
+            clicks,
+            Stats::default(),
+            time.elapsed(),
+        ))
+    }
+
+    fn solve_all(
+        &self,
+        puzzle: &Puzzle,
+        control: &Control,
+    ) -> Result<Option<SolutionSet>, SolvingError> {
+        // Enumerate every answer set, ignoring the minimization of clicks
+        let output = self.run(puzzle, &["0", "--opt-mode=ignore"], control)?;
+
+        let solutions = output
+            .lines()
+            .filter(|line| line.starts_with("action("))
+            .map(|line| clicks_from_string(puzzle, line))
+            .collect::<Result<Vec<_>, _>>()?;
 
-        Ok(())
+        Ok(SolutionSet::from_solutions(
+            solutions,
+            puzzle.grid.modulus(),
+        ))
+    }
+
+    fn optimized(&self) -> Optimize {
+        // The program always minimizes something, the total clicks by default
+        match self.optimize {
+            Optimize::None => Optimize::Clicks,
+            optimize => optimize,
+        }
     }
 }
 
-fn puzzle_to_string(data: &SolverState) -> String {
-    let rows = data.params.rows;
-    let columns = data.params.columns;
-    let states = data.params.states;
-    let objective = data.params.objective;
+fn puzzle_to_string(data: &Puzzle) -> String {
+    let puzzle = &data.grid;
+    let rows = puzzle.rows;
+    let columns = puzzle.columns;
+    let states = puzzle.states;
+    let modulus = puzzle.modulus();
 
-    let mut str = format!("dim({rows},{columns}).states({states}).objective({objective}).");
+    let mut str = format!("dim({rows},{columns}).states({states}).modulus({modulus}).");
+    if puzzle.graph.is_some() {
+        // The edges of a graph cannot be described by offsets, so each affected node is listed
+        for col in 0..columns {
+            for (adj, weight) in puzzle.adjacent(GridCoord { row: 0, col }) {
+                str += &format!("link(1,{},1,{},{weight}).", col + 1, adj.col + 1);
+            }
+        }
+    } else {
+        let tiling = puzzle.tiling;
+        str += &format!("tiling({tiling}).");
+        let mut reach = 0;
+        for class in 0..tiling.classes() {
+            for offset in puzzle.class_offsets(class) {
+                reach = reach.max(offset.row.unsigned_abs().max(offset.col.unsigned_abs()));
+                str += &format!(
+                    "offset({class},{},{},{}).",
+                    offset.row, offset.col, offset.weight
+                );
+            }
+        }
+        str += &format!("reach({reach}).");
+        if puzzle.topology.wraps_rows() {
+            str += "wrap(rows).";
+        }
+        if puzzle.topology.wraps_columns() {
+            str += "wrap(columns).";
+        }
+    }
     for i in 1..=rows {
         for j in 1..=columns {
             let coord = GridCoord {
                 row: i - 1,
                 col: j - 1,
             };
-            let cell = data.params.puzzle[coord].state;
+            if puzzle[coord].disabled {
+                str += format!("disabled({i},{j}).").as_str();
+                continue;
+            }
+            let cell_states = puzzle[coord].modulus;
+            if cell_states != states {
+                str += format!("cell_states({i},{j},{cell_states}).").as_str();
+            }
+            let cell = puzzle[coord].state;
             if cell != 0 {
                 str += format!("cell({i},{j},{cell}).").as_str();
             }
+            let target = data.target[coord].state;
+            if target != 0 {
+                str += format!("target({i},{j},{target}).").as_str();
+            }
         }
     }
 
@@ -103,7 +282,10 @@ fn puzzle_to_string(data: &SolverState) -> String {
 }
 
 #[inline]
-fn solution_from_string(data: &mut SolverState, str: &str) -> Result<(), ParsingError> {
+fn clicks_from_string(puzzle: &Puzzle, str: &str) -> Result<Vec<usize>, ParsingError> {
+    let rows = puzzle.grid.rows;
+    let columns = puzzle.grid.columns;
+
     let mut str = String::from(str);
     str.retain(|c| !c.is_whitespace());
 
@@ -112,18 +294,46 @@ fn solution_from_string(data: &mut SolverState, str: &str) -> Result<(), Parsing
         return Err(ParsingError);
     }
 
+    let mut clicks = vec![0; rows * columns];
     let re = Regex::new(r"action\((?P<i>\d+),(?P<j>\d+),(?P<v>\d+)\)").unwrap();
     for c in re.captures_iter(&str) {
         let i = c.name("i").unwrap().as_str().parse::<usize>().unwrap();
         let j = c.name("j").unwrap().as_str().parse::<usize>().unwrap();
         let v = c.name("v").unwrap().as_str().parse::<usize>().unwrap();
 
-        let coord = GridCoord {
-            row: i - 1,
-            col: j - 1,
-        };
-        data.params.solution[coord].state = v;
+        if !(1..=rows).contains(&i) || !(1..=columns).contains(&j) {
+            return Err(ParsingError);
+        }
+        clicks[(i - 1) * columns + j - 1] = v;
     }
 
-    Ok(())
+    Ok(clicks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::neighbourhood::Tiling;
+    use crate::notation::{tests::cells, Notation};
+    use crate::solver::tests::random_puzzle;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn program_reads_as_asp_facts() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        for tiling in [Tiling::Square, Tiling::Hex, Tiling::Triangle] {
+            let mut puzzle = random_puzzle(4, 5, 3, &mut rng);
+            puzzle.grid.tiling = tiling;
+            puzzle.grid[GridCoord { row: 1, col: 2 }].disabled = true;
+            let program = puzzle_to_string(&puzzle);
+            assert_eq!(Notation::detect(&program), Notation::Asp);
+            assert_eq!(
+                Notation::Asp.read(&program).unwrap(),
+                cells(&puzzle.grid),
+                "{}",
+                program
+            );
+        }
+    }
 }
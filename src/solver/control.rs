@@ -0,0 +1,265 @@
+use crate::data::{Limit, SolvingError, Stats, TimeoutError};
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc,
+};
+use std::time::{Duration, Instant};
+
+/// Steps between two readings of the clock, which would slow the search down if read every time
+const CLOCK_STEPS: u64 = 256;
+
+/// Budgets bounding the work of a solver, `None` meaning unbounded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Wall-clock time the solver can run for
+    pub time: Option<Duration>,
+    /// Steps the solver can carry out, e.g. the candidates checked by the internal solvers
+    pub iterations: Option<u64>,
+}
+
+/// Shared between a solver and its caller, which can follow the progress of the search and stop
+/// it from another thread.
+#[derive(Clone, Debug, Default)]
+pub struct Control {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    cancelled: AtomicBool,
+    limits: Limits,
+    deadline: Option<Instant>,
+    /// Steps carried out since the control was created
+    iterations: AtomicU64,
+    /// Steps of the search carried out so far
+    done: AtomicU64,
+    /// Steps of the whole search, 0 if unknown
+    total: AtomicU64,
+}
+
+impl Control {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A control stopping the solver with `SolvingError::Timeout` once it exhausts `limits`, the
+    /// time limit starting now.
+    pub fn with_limits(limits: Limits) -> Self {
+        let shared = Shared {
+            limits,
+            deadline: limits.time.map(|time| Instant::now() + time),
+            ..Shared::default()
+        };
+        Self {
+            shared: Arc::new(shared),
+        }
+    }
+
+    /// Asks the solver to stop as soon as possible, making it return `SolvingError::Cancelled`.
+    pub fn cancel(&self) {
+        self.shared.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.shared.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Fraction of the search carried out so far, from 0 to 1.
+    pub fn progress(&self) -> f64 {
+        match self.shared.total.load(Ordering::Relaxed) {
+            0 => 0.0,
+            total => (self.shared.done.load(Ordering::Relaxed) as f64 / total as f64).min(1.0),
+        }
+    }
+
+    /// Starts a search made of `total` steps.
+    pub(crate) fn start(&self, total: u64) {
+        self.shared.done.store(0, Ordering::Relaxed);
+        self.shared.total.store(total, Ordering::Relaxed);
+    }
+
+    /// Records `steps` more steps of the search, failing if the search has to stop.
+    pub(crate) fn advance(&self, steps: u64) -> Result<(), SolvingError> {
+        self.shared.done.fetch_add(steps, Ordering::Relaxed);
+        let before = self.shared.iterations.fetch_add(steps, Ordering::Relaxed);
+        self.check_iterations(before + steps)?;
+        if before / CLOCK_STEPS != (before + steps) / CLOCK_STEPS {
+            self.check_time()?;
+        }
+        self.check_cancelled()
+    }
+
+    /// Records up to `steps` more steps, as many as the iteration limit still allows, returning how
+    /// many were recorded. Fails if the search has to stop before any step.
+    pub(crate) fn advance_up_to(&self, steps: u64) -> Result<u64, SolvingError> {
+        let max = self.shared.limits.iterations.unwrap_or(u64::MAX);
+        let before = self
+            .shared
+            .iterations
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |iterations| {
+                (iterations < max).then(|| iterations.saturating_add(steps).min(max))
+            })
+            .map_err(|_| timeout(Limit::Iterations(max)))?;
+        let granted = steps.min(max - before);
+        self.shared.done.fetch_add(granted, Ordering::Relaxed);
+        if before / CLOCK_STEPS != (before + granted) / CLOCK_STEPS {
+            self.check_time()?;
+        }
+        self.check_cancelled()?;
+        Ok(granted)
+    }
+
+    pub(crate) fn check(&self) -> Result<(), SolvingError> {
+        self.check_iterations(self.shared.iterations.load(Ordering::Relaxed))?;
+        self.check_time()?;
+        self.check_cancelled()
+    }
+
+    fn check_cancelled(&self) -> Result<(), SolvingError> {
+        if self.is_cancelled() {
+            Err(SolvingError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_iterations(&self, iterations: u64) -> Result<(), SolvingError> {
+        match self.shared.limits.iterations {
+            Some(max) if iterations > max => Err(timeout(Limit::Iterations(max))),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_time(&self) -> Result<(), SolvingError> {
+        match (self.shared.limits.time, self.shared.deadline) {
+            (Some(time), Some(deadline)) if Instant::now() >= deadline => {
+                Err(timeout(Limit::Time(time)))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The solver fills in the statistics of its search with `SolvingError::with_stats`.
+fn timeout(limit: Limit) -> SolvingError {
+    SolvingError::Timeout(TimeoutError {
+        limit,
+        stats: Stats::default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::SolverState;
+    use crate::settings::Optimize;
+    use crate::solver::{Bitset, Internal, InternalPar, Linear, Solver};
+    use std::thread;
+
+    fn iteration_limit(iterations: u64) -> Control {
+        Control::with_limits(Limits {
+            time: None,
+            iterations: Some(iterations),
+        })
+    }
+
+    fn is_timeout<T>(result: &Result<T, SolvingError>, expected: Limit) -> bool {
+        matches!(result, Err(SolvingError::Timeout(TimeoutError { limit, .. })) if *limit == expected)
+    }
+
+    #[test]
+    fn stops_at_iteration_limit() {
+        let control = iteration_limit(10);
+        control.start(20);
+        assert!(control.advance(4).is_ok());
+        assert!(control.advance(6).is_ok());
+        assert_eq!(control.progress(), 0.5);
+        assert!(is_timeout(&control.advance(1), Limit::Iterations(10)));
+        assert!(is_timeout(&control.check(), Limit::Iterations(10)));
+    }
+
+    #[test]
+    fn grants_steps_up_to_iteration_limit() {
+        let control = iteration_limit(10);
+        assert_eq!(control.advance_up_to(4).unwrap(), 4);
+        assert_eq!(control.advance_up_to(10).unwrap(), 6);
+        assert!(is_timeout(&control.advance_up_to(1), Limit::Iterations(10)));
+
+        let control = Control::new();
+        assert_eq!(control.advance_up_to(u64::MAX).unwrap(), u64::MAX);
+    }
+
+    #[test]
+    fn stops_at_time_limit() {
+        let time = Duration::from_millis(20);
+        let control = Control::with_limits(Limits {
+            time: Some(time),
+            iterations: None,
+        });
+        assert!(control.check().is_ok());
+        thread::sleep(time);
+        assert!(is_timeout(&control.check(), Limit::Time(time)));
+    }
+
+    #[test]
+    fn solvers_stop_at_iteration_limit() {
+        let mut data = SolverState::new(5, 5, 2, 0);
+        data.params.reset_grids();
+        data.randomize_with(0);
+        let puzzle = data.params.to_puzzle();
+        let optimize = Optimize::Clicks;
+        let solvers: [(&str, Box<dyn Solver>); 4] = [
+            ("Internal", Box::new(Internal { optimize })),
+            (
+                "InternalPar",
+                Box::new(InternalPar {
+                    threads: 2,
+                    optimize,
+                }),
+            ),
+            ("Linear", Box::new(Linear { optimize })),
+            ("Bitset", Box::new(Bitset { optimize })),
+        ];
+        for (name, solver) in solvers {
+            let result = solver.solve(&puzzle, &iteration_limit(1));
+            assert!(
+                is_timeout(&result, Limit::Iterations(1)),
+                "{}: {:?}",
+                name,
+                result
+            );
+            // The candidates checked before the timeout are reported, the linear and bitset
+            // solvers running out during the elimination, before checking any
+            if let Err(SolvingError::Timeout(timeout)) = result {
+                let searching = name.starts_with("Internal");
+                assert_eq!(timeout.stats.candidates > 0, searching, "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn stops_when_cancelled() {
+        // Far too many candidates for the search to end by itself
+        let mut data = SolverState::new(8, 12, 7, 0);
+        data.params.reset_grids();
+        data.randomize_with(0);
+        let puzzle = data.params.to_puzzle();
+        let control = Control::new();
+        let search = {
+            let control = control.clone();
+            thread::spawn(move || {
+                Internal {
+                    optimize: Optimize::Clicks,
+                }
+                .solve(&puzzle, &control)
+            })
+        };
+        thread::sleep(Duration::from_millis(50));
+        control.cancel();
+        assert!(matches!(
+            search.join().unwrap(),
+            Err(SolvingError::Cancelled)
+        ));
+        assert!(control.progress() < 1.0);
+    }
+}
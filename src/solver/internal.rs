@@ -1,202 +1,632 @@
-use super::Solver;
-use crate::data::{Grid, GridCoord, SolverState, SolvingError};
+use super::{Control, Solver};
+use crate::data::{
+    Grid, GridCoord, Puzzle, Solution, SolutionSet, SolvingError, Stats, UnsupportedError,
+};
+use crate::neighbourhood::Tiling;
+use crate::settings::Optimize;
 use itertools::Itertools;
 use std::{
     num::NonZeroUsize,
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        mpsc::channel,
-        Arc,
-    },
+    ops::Range,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
     thread,
+    time::Instant,
 };
 
-pub struct Internal;
+/// Chunks of candidates each thread of `InternalPar` takes on average
+const CHUNKS_PER_THREAD: u64 = 64;
+/// Candidates in a chunk, bounding how long a thread goes without checking the limits
+const MAX_CHUNK: u64 = 4096;
+
+pub struct Internal {
+    pub optimize: Optimize,
+}
 
 impl Solver for Internal {
-    fn solve(&self, data: &mut SolverState) -> Result<(), SolvingError> {
-        let puzzle_backup = data.params.puzzle.clone();
-
-        let rows = data.params.rows;
-        let columns = data.params.columns;
-        let states = data.params.states;
-
-        data.params.solution.error = true;
-        if rows < columns {
-            for first_col_clicks in (0..rows).map(|_| 0..states).multi_cartesian_product() {
-                if solve_internal_by_col(data, &puzzle_backup, &first_col_clicks) {
-                    break;
-                }
+    fn solve(&self, puzzle: &Puzzle, control: &Control) -> Result<Solution, SolvingError> {
+        check_supported(&puzzle.grid)?;
+        let time = Instant::now();
+        let mut search = Search::new(puzzle);
+
+        let (col_seeds, row_seeds) = seed_cells(&puzzle.grid);
+        let by_col = col_seeds < row_seeds;
+        let seeds = col_seeds.min(row_seeds);
+        let states = puzzle.grid.states;
+        control.start(candidates(seeds, states));
+
+        let mut best = Best::new(self.optimize);
+        for seed_clicks in seed_clicks(seeds, states) {
+            if let Err(e) = control.advance(1) {
+                best.stopped = Some(e);
+                break;
             }
-        } else {
-            for first_row_clicks in (0..columns).map(|_| 0..states).multi_cartesian_product() {
-                if solve_internal_by_row(data, &puzzle_backup, &first_row_clicks) {
-                    break;
-                }
+            best.stats.candidates += 1;
+            if chase(&mut search, puzzle, &seed_clicks, by_col) && best.offer(&search.clicks) {
+                break;
             }
         }
 
-        Ok(())
+        best.into_solution(puzzle, time)
+    }
+
+    fn solve_all(
+        &self,
+        puzzle: &Puzzle,
+        control: &Control,
+    ) -> Result<Option<SolutionSet>, SolvingError> {
+        check_supported(&puzzle.grid)?;
+        let mut search = Search::new(puzzle);
+
+        let (col_seeds, row_seeds) = seed_cells(&puzzle.grid);
+        let by_col = col_seeds < row_seeds;
+        let seeds = col_seeds.min(row_seeds);
+        let states = puzzle.grid.states;
+        control.start(candidates(seeds, states));
+
+        let mut solutions = vec![];
+        for (candidates, seed_clicks) in seed_clicks(seeds, states).enumerate() {
+            control.advance(1).map_err(|e| {
+                e.with_stats(Stats {
+                    candidates,
+                    solutions: solutions.len(),
+                })
+            })?;
+            if chase(&mut search, puzzle, &seed_clicks, by_col) {
+                solutions.push(search.clicks.clone());
+            }
+        }
+
+        Ok(SolutionSet::from_solutions(solutions, states))
+    }
+
+    fn optimized(&self) -> Optimize {
+        self.optimize
     }
 }
 
 pub struct InternalPar {
     pub threads: usize,
+    pub optimize: Optimize,
 }
 
-impl Solver for InternalPar {
-    fn solve(&self, data: &mut SolverState) -> Result<(), SolvingError> {
-        let threads = if self.threads == 0 {
+impl InternalPar {
+    fn threads(&self) -> usize {
+        if self.threads == 0 {
             thread::available_parallelism()
                 .unwrap_or(NonZeroUsize::new(1).unwrap())
                 .get()
         } else {
             self.threads
-        };
+        }
+    }
+}
 
-        let rows = data.params.rows;
-        let columns = data.params.columns;
-        let states = data.params.states;
+impl Solver for InternalPar {
+    fn solve(&self, puzzle: &Puzzle, control: &Control) -> Result<Solution, SolvingError> {
+        check_supported(&puzzle.grid)?;
+        let time = Instant::now();
+        let threads = self.threads();
 
-        let (tx, rx) = channel();
-        let solution_found = Arc::new(AtomicBool::new(false));
-        let puzzle_backup = Arc::new(data.params.puzzle.clone());
+        let (col_seeds, row_seeds) = seed_cells(&puzzle.grid);
+        let by_col = col_seeds < row_seeds;
+        let seeds = col_seeds.min(row_seeds);
+        let states = puzzle.grid.states;
+        let total = candidates(seeds, states);
+        control.start(total);
 
-        data.params.solution.error = true;
-        if rows < columns {
-            thread::scope(|scope| {
-                for i in 0..threads {
-                    let thread_tx = tx.clone();
-                    let solution_found = solution_found.clone();
+        let optimize = self.optimize;
+        let queue = WorkQueue::new(total, threads);
+        let solution_found = AtomicBool::new(false);
 
-                    let mut data = data.clone();
-                    let puzzle_backup = puzzle_backup.clone();
-                    let first_col_clicks_iter =
-                        (0..rows).map(|_| 0..states).multi_cartesian_product();
+        let bests: Vec<Best> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..threads)
+                .map(|_| {
+                    let queue = &queue;
+                    let solution_found = &solution_found;
+                    let mut search = Search::new(puzzle);
 
                     scope.spawn(move || {
-                        for first_col_clicks in
-                            first_col_clicks_iter.clone().skip(i).step_by(threads)
-                        {
-                            if solve_internal_by_col(&mut data, &puzzle_backup, &first_col_clicks) {
-                                solution_found.store(true, Ordering::Relaxed);
-                                let _ = thread_tx.send(data);
-                                return;
-                            } else if solution_found.load(Ordering::Relaxed) {
-                                return;
+                        let mut best = Best::new(optimize);
+                        'search: while let Some(range) = queue.take() {
+                            let granted = match control.advance_up_to(range.end - range.start) {
+                                Ok(granted) => granted,
+                                Err(e) => {
+                                    best.stopped = Some(e);
+                                    break;
+                                }
+                            };
+                            let mut seed_clicks = decode(range.start, seeds, states);
+                            for _ in 0..granted {
+                                if solution_found.load(Ordering::Acquire) {
+                                    break 'search;
+                                }
+                                best.stats.candidates += 1;
+                                if chase(&mut search, puzzle, &seed_clicks, by_col)
+                                    && best.offer(&search.clicks)
+                                {
+                                    solution_found.store(true, Ordering::Release);
+                                    break 'search;
+                                }
+                                increment(&mut seed_clicks, states);
+                            }
+                            // Out of iterations before the end of the chunk
+                            if let Err(e) = control.advance(range.end - range.start - granted) {
+                                best.stopped = Some(e);
+                                break;
                             }
                         }
-                    });
-                }
-            })
-        } else {
-            thread::scope(|scope| {
-                for i in 0..threads {
-                    let thread_tx = tx.clone();
-                    let solution_found = solution_found.clone();
+                        best
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .filter_map(|handle| handle.join().ok())
+                .collect()
+        });
+
+        let mut best = Best::new(optimize);
+        for other in bests {
+            best.merge(other);
+        }
+        best.into_solution(puzzle, time)
+    }
+
+    fn solve_all(
+        &self,
+        puzzle: &Puzzle,
+        control: &Control,
+    ) -> Result<Option<SolutionSet>, SolvingError> {
+        check_supported(&puzzle.grid)?;
+        let threads = self.threads();
 
-                    let mut data = data.clone();
-                    let puzzle_backup = puzzle_backup.clone();
-                    let first_row_clicks_iter =
-                        (0..columns).map(|_| 0..states).multi_cartesian_product();
+        let (col_seeds, row_seeds) = seed_cells(&puzzle.grid);
+        let by_col = col_seeds < row_seeds;
+        let seeds = col_seeds.min(row_seeds);
+        let states = puzzle.grid.states;
+        let total = candidates(seeds, states);
+        control.start(total);
+
+        let queue = WorkQueue::new(total, threads);
+
+        let searches = thread::scope(|scope| {
+            let handles: Vec<_> = (0..threads)
+                .map(|_| {
+                    let queue = &queue;
+                    let mut search = Search::new(puzzle);
 
                     scope.spawn(move || {
-                        for first_row_clicks in
-                            first_row_clicks_iter.clone().skip(i).step_by(threads)
-                        {
-                            if solve_internal_by_row(&mut data, &puzzle_backup, &first_row_clicks) {
-                                solution_found.store(true, Ordering::Relaxed);
-                                let _ = thread_tx.send(data);
-                                return;
-                            } else if solution_found.load(Ordering::Relaxed) {
-                                return;
+                        let mut solutions = vec![];
+                        let mut candidates = 0;
+                        while let Some(range) = queue.take() {
+                            let granted = match control.advance_up_to(range.end - range.start) {
+                                Ok(granted) => granted,
+                                Err(e) => return (solutions, candidates, Some(e)),
+                            };
+                            let mut seed_clicks = decode(range.start, seeds, states);
+                            for _ in 0..granted {
+                                candidates += 1;
+                                if chase(&mut search, puzzle, &seed_clicks, by_col) {
+                                    solutions.push(search.clicks.clone());
+                                }
+                                increment(&mut seed_clicks, states);
+                            }
+                            // Out of iterations before the end of the chunk
+                            if let Err(e) = control.advance(range.end - range.start - granted) {
+                                return (solutions, candidates, Some(e));
                             }
                         }
-                    });
-                }
-            })
+                        (solutions, candidates, None)
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .filter_map(|handle| handle.join().ok())
+                .collect::<Vec<_>>()
+        });
+
+        let mut solutions = vec![];
+        let mut stats = Stats::default();
+        let mut stopped = None;
+        for (found, candidates, error) in searches {
+            stats.candidates += candidates;
+            stats.solutions += found.len();
+            solutions.extend(found);
+            stopped = stopped.or(error);
+        }
+        if let Some(e) = stopped {
+            return Err(e.with_stats(stats));
+        }
+
+        Ok(SolutionSet::from_solutions(solutions, states))
+    }
+
+    fn optimized(&self) -> Optimize {
+        self.optimize
+    }
+}
+
+/// Hands out the indices of the candidates to the threads of `InternalPar` in chunks, so that
+/// each thread only decodes the candidates it checks and a thread done early takes more work
+/// instead of waiting for the others.
+struct WorkQueue {
+    next: AtomicU64,
+    total: u64,
+    chunk: u64,
+}
+
+impl WorkQueue {
+    fn new(total: u64, threads: usize) -> Self {
+        // Small enough chunks for the threads to finish together, big enough to take them rarely
+        let chunk = (total / (threads as u64 * CHUNKS_PER_THREAD)).clamp(1, MAX_CHUNK);
+        Self {
+            next: AtomicU64::new(0),
+            total,
+            chunk,
         }
+    }
+
+    /// The next chunk of candidates to check, if any is left.
+    fn take(&self) -> Option<Range<u64>> {
+        let start = self.next.fetch_add(self.chunk, Ordering::Relaxed);
+        (start < self.total).then(|| start..start.saturating_add(self.chunk).min(self.total))
+    }
+}
+
+/// Best solution found so far according to `optimize`, along with its cost.
+struct Best {
+    optimize: Optimize,
+    solution: Option<(usize, Vec<usize>)>,
+    stats: Stats,
+    /// Why the search stopped before checking every candidate, if it did
+    stopped: Option<SolvingError>,
+}
 
-        if let Ok(solved) = rx.recv() {
-            *data = solved;
+impl Best {
+    fn new(optimize: Optimize) -> Self {
+        Self {
+            optimize,
+            solution: None,
+            stats: Stats::default(),
+            stopped: None,
         }
+    }
 
+    /// Records the clicks of a solution, returning whether the search can stop.
+    fn offer(&mut self, clicks: &[usize]) -> bool {
+        self.stats.solutions += 1;
+        let cost = self.optimize.cost(clicks);
+        self.keep(cost, clicks.to_vec());
+        self.optimize == Optimize::None
+    }
+
+    fn keep(&mut self, cost: usize, clicks: Vec<usize>) {
+        if self
+            .solution
+            .as_ref()
+            .is_none_or(|(best_cost, _)| cost < *best_cost)
+        {
+            self.solution = Some((cost, clicks));
+        }
+    }
+
+    /// Combines the search of another thread into this one.
+    fn merge(&mut self, other: Best) {
+        self.stats.candidates += other.stats.candidates;
+        self.stats.solutions += other.stats.solutions;
+        self.stopped = self.stopped.take().or(other.stopped);
+        if let Some((cost, clicks)) = other.solution {
+            self.keep(cost, clicks);
+        }
+    }
+
+    /// The best solution found, unless the search stopped before it could be sure of it.
+    fn into_solution(self, puzzle: &Puzzle, time: Instant) -> Result<Solution, SolvingError> {
+        // Any solution will do without optimizing, even if another thread ran out of budget
+        let complete = self.optimize == Optimize::None && self.solution.is_some();
+        match self.stopped {
+            Some(e) if !complete => Err(e.with_stats(self.stats)),
+            _ => {
+                let clicks = self.solution.map(|(_, clicks)| clicks);
+                Ok(Solution::new(puzzle, clicks, self.stats, time.elapsed()))
+            }
+        }
+    }
+}
+
+/// Chasing the lights along rows or columns relies on every cell being a square affected only by
+/// its orthogonal neighbours, and on the clicks needed by a cell being the same for the cell that
+/// has to perform them.
+fn check_supported(grid: &Grid) -> Result<(), UnsupportedError> {
+    if grid.graph.is_some() {
+        Err(UnsupportedError {
+            solver: "Internal",
+            feature: "graph puzzles",
+        })
+    } else if grid.is_mixed() {
+        Err(UnsupportedError {
+            solver: "Internal",
+            feature: "cells with different numbers of states",
+        })
+    } else if grid.tiling != Tiling::Square {
+        Err(UnsupportedError {
+            solver: "Internal",
+            feature: "tilings other than the square one",
+        })
+    } else if !grid.stencil.is_von_neumann() {
+        Err(UnsupportedError {
+            solver: "Internal",
+            feature: "neighbourhoods other than the plus of radius 1",
+        })
+    } else {
         Ok(())
     }
 }
 
+/// Number of columns and rows respectively whose clicks have to be guessed when chasing the
+/// lights along the columns and along the rows.
+///
+/// The clicks on the first column (row) determine the clicks on all the following ones, unless the
+/// grid wraps horizontally (vertically): then the first column (row) is also affected by the last
+/// one, so the clicks on the first two columns (rows) have to be guessed.
+fn seed_lines(grid: &Grid) -> (usize, usize) {
+    let seed_cols = if grid.topology.wraps_columns() { 2 } else { 1 };
+    let seed_rows = if grid.topology.wraps_rows() { 2 } else { 1 };
+    (seed_cols.min(grid.columns), seed_rows.min(grid.rows))
+}
+
+/// Number of cells whose clicks have to be guessed when chasing the lights along the columns and
+/// along the rows respectively.
+///
+/// Besides the enabled cells on the seed lines, the click on an enabled cell following a disabled
+/// one cannot be deduced from it, so it has to be guessed too.
+fn seed_cells(puzzle: &Grid) -> (usize, usize) {
+    let (seed_cols, seed_rows) = seed_lines(puzzle);
+    let mut col_seeds = 0;
+    let mut row_seeds = 0;
+    for row in 0..puzzle.rows {
+        for col in 0..puzzle.columns {
+            if puzzle[GridCoord { row, col }].disabled {
+                continue;
+            }
+            if col < seed_cols || puzzle[GridCoord { row, col: col - 1 }].disabled {
+                col_seeds += 1;
+            }
+            if row < seed_rows || puzzle[GridCoord { row: row - 1, col }].disabled {
+                row_seeds += 1;
+            }
+        }
+    }
+    (col_seeds, row_seeds)
+}
+
+/// Working copy of a puzzle, clicked while chasing the lights of a candidate.
+struct Search {
+    grid: Grid,
+    /// Clicks performed on each cell, row by row
+    clicks: Vec<usize>,
+}
+
+impl Search {
+    fn new(puzzle: &Puzzle) -> Self {
+        Self {
+            grid: puzzle.grid.clone(),
+            clicks: vec![0; puzzle.grid.storage.len()],
+        }
+    }
+}
+
+/// Chases the lights along the columns if `by_col`, along the rows otherwise.
+#[inline]
+fn chase(search: &mut Search, puzzle: &Puzzle, seed_clicks: &[usize], by_col: bool) -> bool {
+    if by_col {
+        solve_internal_by_col(search, puzzle, seed_clicks)
+    } else {
+        solve_internal_by_row(search, puzzle, seed_clicks)
+    }
+}
+
+/// Number of assignments of clicks to `seeds` cells, saturating if it does not fit in a `u64`.
+fn candidates(seeds: usize, states: usize) -> u64 {
+    u32::try_from(seeds)
+        .ok()
+        .and_then(|seeds| (states as u64).checked_pow(seeds))
+        .unwrap_or(u64::MAX)
+}
+
+/// Every assignment of clicks to `seeds` cells, including the empty one when there are no seeds
+/// because every cell is disabled.
+fn seed_clicks(seeds: usize, states: usize) -> impl Iterator<Item = Vec<usize>> + Clone {
+    (seeds == 0)
+        .then(Vec::new)
+        .into_iter()
+        .chain((0..seeds).map(move |_| 0..states).multi_cartesian_product())
+}
+
+/// The clicks on `seeds` cells of the candidate numbered `index` in the order of `seed_clicks`,
+/// read as a number in base `states` whose last digit is the click on the last seed.
+fn decode(mut index: u64, seeds: usize, states: usize) -> Vec<usize> {
+    let mut clicks = vec![0; seeds];
+    for click in clicks.iter_mut().rev() {
+        *click = (index % states as u64) as usize;
+        index /= states as u64;
+    }
+    clicks
+}
+
+/// Moves `clicks` on to the following candidate, wrapping around after the last one.
+#[inline]
+fn increment(clicks: &mut [usize], states: usize) {
+    for click in clicks.iter_mut().rev() {
+        *click += 1;
+        if *click < states {
+            return;
+        }
+        *click = 0;
+    }
+}
+
 #[inline]
 fn solve_internal_by_col(
-    data: &mut SolverState,
-    puzzle_backup: &Grid,
+    search: &mut Search,
+    original: &Puzzle,
     first_col_clicks: &[usize],
 ) -> bool {
-    let puzzle = &mut data.params.puzzle;
-    let solution = &mut data.params.solution;
+    let (seed_cols, _) = seed_lines(&original.grid);
+    let puzzle = &mut search.grid;
+    let solution = &mut search.clicks;
+    let target = &original.target;
 
-    let rows = data.params.rows;
-    let columns = data.params.columns;
-    let states = data.params.states;
-    let objective = data.params.objective;
+    let rows = puzzle.rows;
+    let columns = puzzle.columns;
+    let states = puzzle.states;
 
-    let mut curr_col_clicks = first_col_clicks.to_vec();
+    let mut seeds = first_col_clicks.iter();
     for col in 0..columns {
         for row in 0..rows {
-            solution[GridCoord { row, col }].state = curr_col_clicks[row];
-            puzzle.click_adjacent_unchecked(GridCoord { row, col }, curr_col_clicks[row]);
+            let coord = GridCoord { row, col };
+            let clicks = if puzzle[coord].disabled {
+                0
+            } else if col < seed_cols || puzzle[GridCoord { row, col: col - 1 }].disabled {
+                *seeds.next().unwrap()
+            } else {
+                let left = GridCoord { row, col: col - 1 };
+                let left_cell_state = puzzle[left].state as isize;
+                (target[left].state as isize - left_cell_state).rem_euclid(states as isize) as usize
+            };
+            solution[row * columns + col] = clicks;
+            puzzle.click_adjacent_unchecked(coord, clicks);
         }
-        curr_col_clicks = (0..rows)
-            .map(|row| {
-                let left_cell_state = puzzle[GridCoord { row, col }].state as isize;
-                (objective as isize - left_cell_state).rem_euclid(states as isize) as usize
-            })
-            .collect();
     }
 
-    if puzzle.storage.iter().all(|cell| cell.state == objective) {
-        solution.error = false;
-        *puzzle = puzzle_backup.clone();
-        true
-    } else {
-        *puzzle = puzzle_backup.clone();
-        false
-    }
+    check_target(puzzle, &original.grid, target)
 }
 
 #[inline]
 fn solve_internal_by_row(
-    data: &mut SolverState,
-    puzzle_backup: &Grid,
+    search: &mut Search,
+    original: &Puzzle,
     first_row_clicks: &[usize],
 ) -> bool {
-    let puzzle = &mut data.params.puzzle;
-    let solution = &mut data.params.solution;
+    let (_, seed_rows) = seed_lines(&original.grid);
+    let puzzle = &mut search.grid;
+    let solution = &mut search.clicks;
+    let target = &original.target;
 
-    let rows = data.params.rows;
-    let columns = data.params.columns;
-    let states = data.params.states;
-    let objective = data.params.objective;
+    let rows = puzzle.rows;
+    let columns = puzzle.columns;
+    let states = puzzle.states;
 
-    let mut curr_row_clicks = first_row_clicks.to_vec();
+    let mut seeds = first_row_clicks.iter();
     for row in 0..rows {
         for col in 0..columns {
-            solution[GridCoord { row, col }].state = curr_row_clicks[col];
-            puzzle.click_adjacent_unchecked(GridCoord { row, col }, curr_row_clicks[col]);
+            let coord = GridCoord { row, col };
+            let clicks = if puzzle[coord].disabled {
+                0
+            } else if row < seed_rows || puzzle[GridCoord { row: row - 1, col }].disabled {
+                *seeds.next().unwrap()
+            } else {
+                let top = GridCoord { row: row - 1, col };
+                let top_cell_state = puzzle[top].state as isize;
+                (target[top].state as isize - top_cell_state).rem_euclid(states as isize) as usize
+            };
+            solution[row * columns + col] = clicks;
+            puzzle.click_adjacent_unchecked(coord, clicks);
         }
-        curr_row_clicks = (0..columns)
-            .map(|col| {
-                let top_cell_state = puzzle[GridCoord { row, col }].state as isize;
-                (objective as isize - top_cell_state).rem_euclid(states as isize) as usize
-            })
-            .collect();
     }
 
-    if puzzle.storage.iter().all(|cell| cell.state == objective) {
-        solution.error = false;
-        *puzzle = puzzle_backup.clone();
-        true
-    } else {
-        *puzzle = puzzle_backup.clone();
-        false
+    check_target(puzzle, &original.grid, target)
+}
+
+/// Checks whether every enabled cell of `puzzle` reached its `target` state, then restores
+/// `puzzle`.
+#[inline]
+fn check_target(puzzle: &mut Grid, puzzle_backup: &Grid, target: &Grid) -> bool {
+    let solved = puzzle
+        .storage
+        .iter()
+        .zip(target.storage.iter())
+        .all(|(cell, target)| cell.disabled || cell.state == target.state);
+    *puzzle = puzzle_backup.clone();
+    solved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{SolverState, Topology};
+    use crate::solver::tests::random_puzzle;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    /// Checks that `InternalPar` finds what `Internal` finds on `puzzle`, whatever the split of
+    /// its candidates between threads.
+    fn assert_parallel_agrees(puzzle: &Puzzle) {
+        let expected = Internal {
+            optimize: Optimize::None,
+        }
+        .solve_all(puzzle, &Control::new())
+        .unwrap();
+        let count = expected.as_ref().and_then(|s| s.count());
+        // Powers of 2 and 3 candidates split unevenly between 5 or 7 threads, and 0 picks one per core
+        for threads in [0, 1, 3, 5, 7] {
+            for optimize in [Optimize::None, Optimize::Clicks, Optimize::PressedCells] {
+                let expected = Internal { optimize }
+                    .solve(puzzle, &Control::new())
+                    .unwrap();
+                let solution = InternalPar { threads, optimize }
+                    .solve(puzzle, &Control::new())
+                    .unwrap();
+                assert_eq!(solution.solvable, expected.solvable, "{:?}", puzzle);
+                assert_eq!(
+                    optimize.cost(&solution.clicks),
+                    optimize.cost(&expected.clicks),
+                    "{} threads, {:?}",
+                    threads,
+                    puzzle
+                );
+                if count == Some(1) {
+                    assert_eq!(solution.clicks, expected.clicks, "{:?}", puzzle);
+                }
+            }
+            let solutions = InternalPar {
+                threads,
+                optimize: Optimize::None,
+            }
+            .solve_all(puzzle, &Control::new())
+            .unwrap();
+            assert_eq!(
+                solutions.and_then(|s| s.count()),
+                count,
+                "{} threads, {:?}",
+                threads,
+                puzzle
+            );
+        }
+    }
+
+    #[test]
+    fn parallel_agrees_with_internal() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        for states in [2, 3] {
+            for _ in 0..20 {
+                let (rows, columns) = (rng.gen_range(2..=4), rng.gen_range(2..=4));
+                assert_parallel_agrees(&random_puzzle(rows, columns, states, &mut rng));
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_agrees_with_internal_on_torus() {
+        for seed in 0..5 {
+            let mut data = SolverState::new(3, 4, 3, 0);
+            data.params.topology = Topology::Torus;
+            data.params.reset_grids();
+            data.randomize_with(seed);
+            assert_parallel_agrees(&data.params.to_puzzle());
+        }
     }
 }
@@ -0,0 +1,574 @@
+use super::{Control, Solver, MAX_OPTIMIZED};
+use crate::data::{
+    Grid, GridCoord, Puzzle, QuietPattern, Solution, SolutionSet, SolvingError, Stats,
+    UnsupportedError,
+};
+use crate::settings::Optimize;
+use std::time::Instant;
+
+pub struct Linear {
+    pub optimize: Optimize,
+}
+
+impl Solver for Linear {
+    fn solve(&self, puzzle: &Puzzle, control: &Control) -> Result<Solution, SolvingError> {
+        let time = Instant::now();
+        let grid = &puzzle.grid;
+        let modulus = grid.modulus();
+        let matrix = click_matrix(grid, modulus);
+        let rhs = puzzle_rhs(grid, &puzzle.target, modulus);
+
+        let (clicks, stats) = match solve_mod(&matrix, &rhs, modulus, control)? {
+            Some(solutions) => {
+                let (clicks, stats) = self.best(&solutions, control)?;
+                (Some(clicks), stats)
+            }
+            None => (None, Stats::default()),
+        };
+
+        Ok(Solution::new(puzzle, clicks, stats, time.elapsed()))
+    }
+
+    fn solve_all(
+        &self,
+        puzzle: &Puzzle,
+        control: &Control,
+    ) -> Result<Option<SolutionSet>, SolvingError> {
+        let grid = &puzzle.grid;
+        let modulus = grid.modulus();
+        solve_mod(
+            &click_matrix(grid, modulus),
+            &puzzle_rhs(grid, &puzzle.target, modulus),
+            modulus,
+            control,
+        )
+    }
+
+    fn optimized(&self) -> Optimize {
+        self.optimize
+    }
+}
+
+impl Linear {
+    /// Searches the coset of solutions for the one minimizing `optimize`.
+    fn best(
+        &self,
+        solutions: &SolutionSet,
+        control: &Control,
+    ) -> Result<(Vec<usize>, Stats), SolvingError> {
+        let count = solutions.count();
+        if self.optimize == Optimize::None {
+            let stats = Stats {
+                candidates: 1,
+                solutions: count.unwrap_or(usize::MAX),
+            };
+            return Ok((solutions.particular.clone(), stats));
+        }
+        let count = count
+            .filter(|&count| count <= MAX_OPTIMIZED)
+            .ok_or(UnsupportedError {
+                solver: "Linear",
+                feature: "optimizing puzzles with more than 2^24 solutions",
+            })?;
+
+        control.start(count as u64);
+        let mut best = (usize::MAX, solutions.particular.clone());
+        for index in 0..count {
+            control.advance(1).map_err(|e| {
+                e.with_stats(Stats {
+                    candidates: index,
+                    solutions: count,
+                })
+            })?;
+            let clicks = solutions.nth(index);
+            let cost = self.optimize.cost(&clicks);
+            if cost < best.0 {
+                best = (cost, clicks);
+            }
+        }
+        let stats = Stats {
+            candidates: count,
+            solutions: count,
+        };
+        Ok((best.1, stats))
+    }
+}
+
+/// Tells whether puzzles sharing a layout can be solved without solving them: the clicks the cells
+/// of a puzzle need are a combination of the columns of the click matrix if and only if they are
+/// orthogonal to the kernel of its transpose, which is found once for all.
+#[derive(Clone, Debug)]
+pub struct Solvability {
+    /// The cells determine the click matrix
+    layout: Vec<CellLayout>,
+    modulus: usize,
+    /// Generators of the kernel of the transposed click matrix
+    checks: Vec<Vec<usize>>,
+}
+
+impl Solvability {
+    pub fn new(puzzle: &Grid) -> Self {
+        let modulus = puzzle.modulus();
+        let matrix = click_matrix(puzzle, modulus);
+        let transposed: Vec<Vec<usize>> = (0..matrix.len())
+            .map(|j| matrix.iter().map(|row| row[j]).collect())
+            .collect();
+        // Without limits the elimination always runs to the end
+        let checks = solve_mod(
+            &transposed,
+            &vec![0; matrix.len()],
+            modulus,
+            &Control::new(),
+        )
+        .ok()
+        .flatten()
+        .map(|kernel| {
+            kernel
+                .quiet_patterns
+                .into_iter()
+                .map(|pattern| pattern.clicks)
+                .collect()
+        })
+        .unwrap_or_default();
+        Self {
+            layout: layout(puzzle),
+            modulus,
+            checks,
+        }
+    }
+
+    /// Whether `puzzle` has the layout the checks were found for.
+    pub fn applies_to(&self, puzzle: &Grid) -> bool {
+        self.modulus == puzzle.modulus() && self.layout == layout(puzzle)
+    }
+
+    /// Whether `puzzle` can reach `target`, both having the layout the checks were found for.
+    pub fn is_solvable(&self, puzzle: &Grid, target: &Grid) -> bool {
+        let rhs = puzzle_rhs(puzzle, target, self.modulus);
+        self.checks.iter().all(|check| {
+            check
+                .iter()
+                .zip(&rhs)
+                .map(|(c, r)| mul_mod(*c, *r, self.modulus))
+                .fold(0, |sum, x| (sum + x) % self.modulus)
+                == 0
+        })
+    }
+}
+
+/// Cells affected by a click on a cell, along with whether the cell is disabled and its states
+type CellLayout = (Vec<(GridCoord, usize)>, bool, usize);
+
+fn layout(puzzle: &Grid) -> Vec<CellLayout> {
+    (0..puzzle.rows)
+        .flat_map(|row| (0..puzzle.columns).map(move |col| GridCoord { row, col }))
+        .map(|coord| {
+            let cell = &puzzle[coord];
+            (puzzle.adjacent(coord), cell.disabled, cell.modulus)
+        })
+        .collect()
+}
+
+/// Builds the matrix whose entry `(i, j)` is the increment that a click on cell `j` applies to
+/// cell `i`, cells being indexed in row-major order.
+///
+/// The equation of a cell with `m` states holds modulo `m`, so it is multiplied by `modulus / m`
+/// to make it hold modulo `modulus` like all the others. The equation of a disabled cell is
+/// replaced by `x_j = 0`, so that it is never clicked.
+fn click_matrix(puzzle: &Grid, modulus: usize) -> Vec<Vec<usize>> {
+    let cells = puzzle.rows * puzzle.columns;
+    let mut matrix = vec![vec![0; cells]; cells];
+    for row in 0..puzzle.rows {
+        for col in 0..puzzle.columns {
+            let j = row * puzzle.columns + col;
+            if puzzle[GridCoord { row, col }].disabled {
+                matrix[j][j] = 1;
+            }
+            for (adj, weight) in puzzle.adjacent(GridCoord { row, col }) {
+                matrix[adj.row * puzzle.columns + adj.col][j] +=
+                    weight % puzzle[adj].modulus * (modulus / puzzle[adj].modulus);
+            }
+        }
+    }
+    matrix
+}
+
+/// The clicks each cell needs to receive from its neighbourhood to reach its `target` state,
+/// scaled like the rows of `click_matrix`.
+fn puzzle_rhs(puzzle: &Grid, target: &Grid, modulus: usize) -> Vec<usize> {
+    puzzle
+        .storage
+        .iter()
+        .zip(target.storage.iter())
+        .map(|(cell, target)| {
+            if cell.disabled {
+                0
+            } else {
+                let states = cell.modulus;
+                (target.state + states - cell.state) % states * (modulus / states)
+            }
+        })
+        .collect()
+}
+
+/// Solves `matrix * x = rhs` over the integers modulo `modulus`, returning a particular solution
+/// together with a set of generators for the kernel of `matrix`.
+///
+/// `Z/modulus` is not a field when `modulus` is composite, so the system is solved separately
+/// modulo each prime power dividing `modulus` and the partial solutions are recombined with the
+/// chinese remainder theorem.
+fn solve_mod(
+    matrix: &[Vec<usize>],
+    rhs: &[usize],
+    modulus: usize,
+    control: &Control,
+) -> Result<Option<SolutionSet>, SolvingError> {
+    let mut particular = vec![0; matrix.first().map_or(0, Vec::len)];
+    let mut quiet_patterns = vec![];
+    let factors = factorize(modulus);
+    control.start((matrix.len() * factors.len()) as u64);
+    for (prime, exp) in factors {
+        let echelon = Echelon::new(matrix, rhs, prime, exp, Some(control));
+        control.check()?;
+        let idempotent = crt_idempotent(prime.pow(exp), modulus);
+
+        let Some(solution) = echelon.solution() else {
+            return Ok(None);
+        };
+        for (x, p) in particular.iter_mut().zip(solution) {
+            *x = (*x + mul_mod(p, idempotent, modulus)) % modulus;
+        }
+        for (clicks, order) in echelon.kernel() {
+            quiet_patterns.push(QuietPattern {
+                clicks: lift(clicks, idempotent, modulus),
+                order,
+            });
+        }
+    }
+
+    Ok(Some(SolutionSet {
+        particular,
+        quiet_patterns,
+        modulus,
+    }))
+}
+
+/// Reduces `vectors` to a set of independent generators of their span modulo `modulus`.
+pub(crate) fn span(vectors: &[Vec<usize>], modulus: usize) -> Vec<QuietPattern> {
+    let zeros = vec![0; vectors.len()];
+    let mut generators = vec![];
+    for (prime, exp) in factorize(modulus) {
+        let echelon = Echelon::new(vectors, &zeros, prime, exp, None);
+        let idempotent = crt_idempotent(prime.pow(exp), modulus);
+
+        for (row, &(v, _)) in echelon.a.iter().zip(&echelon.pivots) {
+            generators.push(QuietPattern {
+                clicks: lift(echelon.unpermute(row), idempotent, modulus),
+                order: prime.pow(exp - v),
+            });
+        }
+    }
+    generators
+}
+
+/// Row echelon form of a linear system over `Z/prime^exp`.
+///
+/// Gaussian elimination is carried out choosing, at every step, the pivot with the smallest
+/// `prime`-adic valuation in the remaining submatrix: this way every entry on the right of a pivot
+/// is a multiple of it and back substitution only has to check a divisibility condition.
+struct Echelon {
+    prime: usize,
+    exp: u32,
+    modulus: usize,
+    a: Vec<Vec<usize>>,
+    b: Vec<usize>,
+    /// Original index of each (swapped) column
+    perm: Vec<usize>,
+    /// Valuation of each pivot and inverse of its unit part
+    pivots: Vec<(u32, usize)>,
+}
+
+impl Echelon {
+    /// Reduces the system, giving up early (with a meaningless result) if `control` gets
+    /// cancelled.
+    fn new(
+        matrix: &[Vec<usize>],
+        rhs: &[usize],
+        prime: usize,
+        exp: u32,
+        control: Option<&Control>,
+    ) -> Self {
+        let modulus = prime.pow(exp);
+        let rows = matrix.len();
+        let columns = matrix.first().map_or(0, Vec::len);
+
+        let mut a: Vec<Vec<usize>> = matrix
+            .iter()
+            .map(|row| row.iter().map(|x| x % modulus).collect())
+            .collect();
+        let mut b: Vec<usize> = rhs.iter().map(|x| x % modulus).collect();
+        let mut perm: Vec<usize> = (0..columns).collect();
+        let mut pivots = vec![];
+
+        let mut rank = 0;
+        while rank < rows && rank < columns {
+            if control.is_some_and(|control| control.advance(1).is_err()) {
+                break;
+            }
+            let mut best: Option<(usize, usize, u32)> = None;
+            'search: for (i, row) in a.iter().enumerate().skip(rank) {
+                for (j, &x) in row.iter().enumerate().skip(rank) {
+                    if x != 0 {
+                        let v = valuation(x, prime);
+                        if best.is_none_or(|(_, _, best_v)| v < best_v) {
+                            best = Some((i, j, v));
+                            if v == 0 {
+                                break 'search;
+                            }
+                        }
+                    }
+                }
+            }
+            let Some((i, j, v)) = best else {
+                break;
+            };
+
+            a.swap(rank, i);
+            b.swap(rank, i);
+            perm.swap(rank, j);
+            a.iter_mut().for_each(|row| row.swap(rank, j));
+
+            let scale = prime.pow(v);
+            let unit_inv = inverse(a[rank][rank] / scale, modulus);
+            let (top, bottom) = a.split_at_mut(rank + 1);
+            let pivot_row = &top[rank];
+            let pivot_rhs = b[rank];
+            for (row, rhs) in bottom.iter_mut().zip(&mut b[rank + 1..]) {
+                if row[rank] == 0 {
+                    continue;
+                }
+                let factor = mul_mod(row[rank] / scale, unit_inv, modulus);
+                for (x, &p) in row.iter_mut().zip(pivot_row).skip(rank) {
+                    *x = (*x + modulus - mul_mod(factor, p, modulus)) % modulus;
+                }
+                *rhs = (*rhs + modulus - mul_mod(factor, pivot_rhs, modulus)) % modulus;
+            }
+
+            pivots.push((v, unit_inv));
+            rank += 1;
+        }
+        a.truncate(rank);
+
+        Self {
+            prime,
+            exp,
+            modulus,
+            a,
+            b,
+            perm,
+            pivots,
+        }
+    }
+
+    /// A solution of the system with all the free variables set to 0.
+    fn solution(&self) -> Option<Vec<usize>> {
+        let rank = self.pivots.len();
+        if self.b[rank..].iter().any(|&x| x != 0) {
+            return None;
+        }
+
+        let mut x = vec![0; self.perm.len()];
+        self.back_substitute(&mut x, &self.b, rank)?;
+        Some(self.unpermute(&x))
+    }
+
+    /// Generators of the kernel of the matrix, each with its additive order.
+    ///
+    /// There is one generator for each free variable and one for each pivot that is not a unit,
+    /// since a multiple of `prime^(exp - v)` multiplied by a pivot of valuation `v` vanishes.
+    fn kernel(&self) -> Vec<(Vec<usize>, usize)> {
+        let rank = self.pivots.len();
+        let zeros = vec![0; rank];
+        let mut generators = vec![];
+
+        for free in rank..self.perm.len() {
+            let mut x = vec![0; self.perm.len()];
+            x[free] = 1;
+            self.back_substitute(&mut x, &zeros, rank);
+            generators.push((self.unpermute(&x), self.modulus));
+        }
+        for (i, &(v, _)) in self.pivots.iter().enumerate() {
+            if v > 0 {
+                let mut x = vec![0; self.perm.len()];
+                x[i] = self.prime.pow(self.exp - v);
+                self.back_substitute(&mut x, &zeros, i);
+                generators.push((self.unpermute(&x), self.prime.pow(v)));
+            }
+        }
+
+        generators
+    }
+
+    /// Sets the variables of the first `pivots` pivots so that the corresponding equations, with
+    /// right-hand side `rhs`, are satisfied given the values of the following variables.
+    fn back_substitute(&self, x: &mut [usize], rhs: &[usize], pivots: usize) -> Option<()> {
+        let modulus = self.modulus;
+        for (i, &(v, unit_inv)) in self.pivots[..pivots].iter().enumerate().rev() {
+            let sum = (i + 1..x.len()).fold(0, |acc, j| {
+                (acc + mul_mod(self.a[i][j], x[j], modulus)) % modulus
+            });
+            let rest = (rhs[i] + modulus - sum) % modulus;
+            let scale = self.prime.pow(v);
+            if !rest.is_multiple_of(scale) {
+                return None;
+            }
+            x[i] = mul_mod(rest / scale, unit_inv, modulus) % self.prime.pow(self.exp - v);
+        }
+        Some(())
+    }
+
+    fn unpermute(&self, x: &[usize]) -> Vec<usize> {
+        let mut res = vec![0; x.len()];
+        for (&idx, &value) in self.perm.iter().zip(x) {
+            res[idx] = value;
+        }
+        res
+    }
+}
+
+/// The value which is 1 modulo `prime_power` and 0 modulo the other prime powers of `modulus`.
+fn crt_idempotent(prime_power: usize, modulus: usize) -> usize {
+    let cofactor = modulus / prime_power;
+    mul_mod(
+        cofactor,
+        inverse(cofactor % prime_power, prime_power),
+        modulus,
+    )
+}
+
+fn lift(x: Vec<usize>, idempotent: usize, modulus: usize) -> Vec<usize> {
+    x.into_iter()
+        .map(|x| mul_mod(x, idempotent, modulus))
+        .collect()
+}
+
+fn factorize(mut n: usize) -> Vec<(usize, u32)> {
+    let mut factors = vec![];
+    let mut p = 2;
+    while p * p <= n {
+        let mut exp = 0;
+        while n.is_multiple_of(p) {
+            n /= p;
+            exp += 1;
+        }
+        if exp > 0 {
+            factors.push((p, exp));
+        }
+        p += 1;
+    }
+    if n > 1 {
+        factors.push((n, 1));
+    }
+    factors
+}
+
+fn valuation(mut x: usize, prime: usize) -> u32 {
+    let mut v = 0;
+    while x.is_multiple_of(prime) {
+        x /= prime;
+        v += 1;
+    }
+    v
+}
+
+#[inline]
+fn mul_mod(a: usize, b: usize, modulus: usize) -> usize {
+    (a as u128 * b as u128 % modulus as u128) as usize
+}
+
+/// Inverse of `a` modulo `modulus`, assuming they are coprime.
+fn inverse(a: usize, modulus: usize) -> usize {
+    let (mut old_r, mut r) = (a as i128, modulus as i128);
+    let (mut old_s, mut s) = (1i128, 0i128);
+    while r != 0 {
+        let q = old_r / r;
+        (old_r, r) = (r, old_r - q * r);
+        (old_s, s) = (s, old_s - q * s);
+    }
+    old_s.rem_euclid(modulus as i128) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::SolverState;
+    use crate::neighbourhood::Neighbourhood;
+    use crate::solver::{tests::random_puzzle, Internal};
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn agrees_with_internal() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        // Composite numbers of states are solved modulo each prime power
+        for states in [2, 3, 4, 6] {
+            for _ in 0..50 {
+                let size = if states > 3 { 1..=3 } else { 1..=4 };
+                let (rows, columns) = (rng.gen_range(size.clone()), rng.gen_range(size));
+                let puzzle = random_puzzle(rows, columns, states, &mut rng);
+                for optimize in [Optimize::Clicks, Optimize::PressedCells] {
+                    let expected = Internal { optimize }
+                        .solve(&puzzle, &Control::new())
+                        .unwrap();
+                    let solution = Linear { optimize }.solve(&puzzle, &Control::new()).unwrap();
+                    assert_eq!(solution.solvable, expected.solvable, "{:?}", puzzle);
+                    assert_eq!(
+                        optimize.cost(&solution.clicks),
+                        optimize.cost(&expected.clicks),
+                        "{:?}",
+                        puzzle
+                    );
+                }
+                let expected = Internal {
+                    optimize: Optimize::None,
+                }
+                .solve_all(&puzzle, &Control::new())
+                .unwrap();
+                let solutions = Linear {
+                    optimize: Optimize::None,
+                }
+                .solve_all(&puzzle, &Control::new())
+                .unwrap();
+                assert_eq!(
+                    solutions.and_then(|s| s.count()),
+                    expected.and_then(|s| s.count()),
+                    "{:?}",
+                    puzzle
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn too_many_solutions_to_optimize() {
+        // Clicks affecting no cell make every click pattern a solution of a solved puzzle
+        let mut data = SolverState::new(5, 5, 2, 0);
+        data.params.neighbourhood = Neighbourhood::Custom;
+        data.params.mask = Grid::new(3, 3, 2);
+        data.params.reset_grids();
+        let puzzle = data.params.to_puzzle();
+
+        let solution = Linear {
+            optimize: Optimize::None,
+        }
+        .solve(&puzzle, &Control::new())
+        .unwrap();
+        assert_eq!(solution.stats.solutions, 1 << 25);
+
+        let result = Linear {
+            optimize: Optimize::Clicks,
+        }
+        .solve(&puzzle, &Control::new());
+        assert!(matches!(result, Err(SolvingError::Unsupported(_))));
+    }
+}
@@ -1,8 +1,57 @@
+mod bitset;
 mod clingo;
+mod control;
 mod internal;
+mod linear;
 mod solver_trait;
 
+pub use bitset::Bitset;
 pub use clingo::Clingo;
+pub use control::{Control, Limits};
 pub use internal::Internal;
 pub use internal::InternalPar;
+pub(crate) use linear::span;
+pub use linear::{Linear, Solvability};
 pub use solver_trait::Solver;
+
+/// Solutions of a puzzle searched at most for the optimal one, the search visiting each of them
+const MAX_OPTIMIZED: usize = 1 << 24;
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::data::{Puzzle, SolverState, Topology};
+    use rand::Rng;
+
+    /// A random puzzle of `rows` by `columns` cells, with random holes, states and target.
+    pub(crate) fn random_puzzle(
+        rows: usize,
+        columns: usize,
+        states: usize,
+        rng: &mut impl Rng,
+    ) -> Puzzle {
+        let mut data = SolverState::new(rows, columns, states, 0);
+        data.params.topology = [
+            Topology::Bounded,
+            Topology::Torus,
+            Topology::HorizontalCylinder,
+            Topology::VerticalCylinder,
+        ][rng.gen_range(0..4)];
+        data.params.reset_grids();
+        let layout: Vec<(bool, usize)> = (0..rows * columns)
+            .map(|_| (rng.gen_bool(0.2), states))
+            .collect();
+        let mut random_states = || -> Vec<usize> {
+            (0..rows * columns)
+                .map(|_| rng.gen_range(0..states))
+                .collect()
+        };
+        let (puzzle, target) = (random_states(), random_states());
+        let params = &mut data.params;
+        params.puzzle.set_cell_states(&puzzle);
+        params.target.set_cell_states(&target);
+        for grid in [&mut params.puzzle, &mut params.target, &mut params.solution] {
+            grid.set_layout(&layout);
+        }
+        data.params.to_puzzle()
+    }
+}
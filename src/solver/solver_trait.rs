@@ -1,5 +1,17 @@
-use crate::data::{SolverState, SolvingError};
+use super::Control;
+use crate::data::{Puzzle, Solution, SolutionSet, SolvingError};
+use crate::settings::Optimize;
 
-pub trait Solver {
-    fn solve(&self, data: &mut SolverState) -> Result<(), SolvingError>;
+pub trait Solver: Send + Sync {
+    /// Solves `puzzle`, returning the clicks that minimize `optimized`.
+    ///
+    /// The search reports its progress to `control` and stops if it gets cancelled.
+    fn solve(&self, puzzle: &Puzzle, control: &Control) -> Result<Solution, SolvingError>;
+    fn solve_all(
+        &self,
+        puzzle: &Puzzle,
+        control: &Control,
+    ) -> Result<Option<SolutionSet>, SolvingError>;
+    /// The metric minimized by the solutions returned by `solve`
+    fn optimized(&self) -> Optimize;
 }
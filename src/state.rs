@@ -0,0 +1,269 @@
+//! State of the window: the plain puzzle types of the library wrapped in the `Data` and `Lens`
+//! types of the widgets, along with what only the window shows.
+use druid::{Data, Lens};
+use lights_out::data::{Grid, Params, Solution, SolutionSet, SolverState, SolvingError};
+use lights_out::settings::{Optimize, Settings};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Edits the history goes back through
+const HISTORY_LENGTH: usize = 100;
+
+#[derive(Clone, Data, Lens)]
+pub struct AppState {
+    pub params: ParamsState,
+    /// Whether a solver or the generator is running in the background
+    pub busy: bool,
+    /// Fraction of the search carried out by the running solver or generator
+    pub progress: f64,
+    /// Whether generated puzzles must have a single optimal solution
+    pub unique: bool,
+    history: History,
+}
+
+/// Puzzles edited before (and after, once undone) the current one.
+#[derive(Clone, Default, Data)]
+struct History {
+    undo: Arc<Vec<ParamsState>>,
+    redo: Arc<Vec<ParamsState>>,
+}
+
+impl AppState {
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            params: ParamsState::new(SolverState::from_settings(settings)),
+            busy: false,
+            progress: 0.0,
+            unique: false,
+            history: History::default(),
+        }
+    }
+
+    /// Records the parameters the puzzle had `before` an edit, so that `undo` can bring them back.
+    /// Nothing is recorded if the edit left the puzzle, its target and its layout unchanged, e.g.
+    /// if it only solved the puzzle.
+    pub fn record(&mut self, before: ParamsState) {
+        if before.params().same_puzzle(self.params.params()) {
+            return;
+        }
+        let undo = Arc::make_mut(&mut self.history.undo);
+        if undo.len() == HISTORY_LENGTH {
+            undo.remove(0);
+        }
+        undo.push(before);
+        if !self.history.redo.is_empty() {
+            self.history.redo = Arc::default();
+        }
+    }
+
+    /// Goes back to the puzzle before the last recorded edit, returning false if there is none.
+    pub fn undo(&mut self) -> bool {
+        match Arc::make_mut(&mut self.history.undo).pop() {
+            Some(params) => {
+                let current = self.restore(params);
+                Arc::make_mut(&mut self.history.redo).push(current);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Goes forward to the puzzle of the last undone edit, returning false if there is none.
+    pub fn redo(&mut self) -> bool {
+        match Arc::make_mut(&mut self.history.redo).pop() {
+            Some(params) => {
+                let current = self.restore(params);
+                Arc::make_mut(&mut self.history.undo).push(current);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replaces the parameters with `params`, staying in or out of play mode, and returns the
+    /// replaced ones.
+    fn restore(&mut self, mut params: ParamsState) -> ParamsState {
+        params.play = self.params.play;
+        std::mem::replace(&mut self.params, params)
+    }
+}
+
+/// The puzzle being edited and solved, along with how the window shows it.
+#[derive(Clone, Data, Lens)]
+pub struct ParamsState {
+    #[data(same_fn = "PartialEq::eq")]
+    pub state: SolverState,
+    /// Whether clicks on the puzzle change every cell they affect, instead of editing the clicked
+    /// one
+    pub play: bool,
+    /// Path of the graph file typed in, read when the graph is loaded
+    pub graph_path: String,
+    /// Whether the puzzle can reach the target, if known
+    pub solvable: Option<bool>,
+    pub solve_time: String,
+    pub solve_error: String,
+    /// Whether the solver failed or found no solution, which paints the solution grid red
+    pub failed: bool,
+    /// Solution shown among those found by the solver
+    pub solution_index: usize,
+}
+
+impl ParamsState {
+    pub fn new(state: SolverState) -> Self {
+        let graph_path = match &state.params.graph {
+            Some(graph) => graph.path.clone(),
+            None => String::new(),
+        };
+        Self {
+            state,
+            play: false,
+            graph_path,
+            solvable: None,
+            solve_time: String::new(),
+            solve_error: String::new(),
+            failed: false,
+            solution_index: 0,
+        }
+    }
+
+    pub fn params(&self) -> &Params {
+        &self.state.params
+    }
+
+    pub fn params_mut(&mut self) -> &mut Params {
+        &mut self.state.params
+    }
+
+    /// Randomizes the puzzle with a new seed.
+    pub fn randomize(&mut self) {
+        self.randomize_with(rand::random());
+    }
+
+    pub fn randomize_with(&mut self, seed: u32) {
+        self.state.randomize_with(seed);
+        self.clear_solve_info();
+    }
+
+    /// Forgets how the last solver run went, the puzzle having been replaced.
+    pub fn clear_solve_info(&mut self) {
+        self.solve_time.clear();
+        self.solve_error.clear();
+    }
+
+    pub fn clear_solution(&mut self) {
+        self.solve_time.clear();
+        self.failed = false;
+        self.state.clear_solution();
+    }
+
+    /// Shows the result of a solver optimizing `optimized`, run in the background.
+    pub fn set_solution(&mut self, result: &Result<Solution, SolvingError>, optimized: Optimize) {
+        self.state.set_solution(result, optimized);
+        if let Ok(solution) = result {
+            self.solve_time = format!("{:?}", solution.time);
+        }
+        self.failed = !matches!(result, Ok(solution) if solution.solvable);
+        self.set_solve_error(result);
+    }
+
+    /// Shows the solutions found in `time`, run in the background.
+    pub fn set_solutions(
+        &mut self,
+        result: Result<Option<SolutionSet>, SolvingError>,
+        time: Duration,
+    ) {
+        self.solve_time = format!("{:?}", time);
+        self.set_solve_error(&result);
+        self.solution_index = 0;
+        self.failed = !matches!(self.state.set_solutions(result), Ok(true));
+    }
+
+    fn set_solve_error<T>(&mut self, result: &Result<T, SolvingError>) {
+        self.solve_error = match result {
+            Ok(_) => String::new(),
+            Err(e) => e.to_string(),
+        };
+    }
+
+    pub fn step_solution(&mut self, forward: bool) {
+        if let Some(solutions) = &self.state.params.solutions {
+            let index = match (solutions.count(), forward) {
+                (Some(count), true) => (self.solution_index + 1) % count,
+                (Some(count), false) => (self.solution_index + count - 1) % count,
+                (None, true) => self.solution_index.saturating_add(1),
+                (None, false) => self.solution_index.saturating_sub(1),
+            };
+            self.solution_index = index;
+            self.state.params.show_solution(index);
+            self.failed = false;
+        }
+    }
+}
+
+/// A grid as the grid and graph widgets draw it.
+#[derive(Clone, Data, Lens)]
+pub struct Board {
+    #[data(same_fn = "PartialEq::eq")]
+    pub grid: Grid,
+    /// Whether clicks change every cell they affect, instead of editing the clicked one
+    pub play: bool,
+    /// Whether to paint the grid red
+    pub failed: bool,
+}
+
+/// The grids of the parameters, each drawn on a `Board`. Editing any grid but the solution by hand
+/// forgets the seed of the puzzle.
+#[derive(Clone, Copy, PartialEq)]
+pub enum BoardLens {
+    Puzzle,
+    Target,
+    Solution,
+    Mask,
+}
+
+impl Lens<ParamsState, Board> for BoardLens {
+    fn with<V, F: FnOnce(&Board) -> V>(&self, data: &ParamsState, f: F) -> V {
+        let params = data.params();
+        let grid = match self {
+            BoardLens::Puzzle => &params.puzzle,
+            BoardLens::Target => &params.target,
+            BoardLens::Solution => &params.solution,
+            BoardLens::Mask => &params.mask,
+        };
+        f(&Board {
+            grid: grid.clone(),
+            play: data.play && *self == BoardLens::Puzzle,
+            failed: data.failed && *self == BoardLens::Solution,
+        })
+    }
+
+    fn with_mut<V, F: FnOnce(&mut Board) -> V>(&self, data: &mut ParamsState, f: F) -> V {
+        let mut board = self.with(data, Board::clone);
+        let value = f(&mut board);
+        let params = data.params_mut();
+        let grid = match self {
+            BoardLens::Puzzle => &mut params.puzzle,
+            BoardLens::Target => &mut params.target,
+            BoardLens::Solution => &mut params.solution,
+            BoardLens::Mask => &mut params.mask,
+        };
+        if *grid != board.grid {
+            *grid = board.grid;
+            if *self != BoardLens::Solution {
+                params.seed = None;
+            }
+        }
+        value
+    }
+}
+
+/// A plain library value compared with `PartialEq`, so that widgets such as `RadioGroup` can
+/// hold it.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Choice<T>(pub T);
+
+impl<T: Clone + PartialEq + 'static> Data for Choice<T> {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
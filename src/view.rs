@@ -1,16 +1,65 @@
-use crate::controllers::{ParamsController, PlayController};
-use crate::data::{Params, SolverState};
-use crate::formatters::NonZeroFormatter;
-use crate::widgets::GridWidget;
-use crate::{nonzero_textbox, usize_textbox};
+use crate::controllers::{
+    BusyController, FileController, HistoryController, LayoutController, ParamsController,
+    SeedController, SolvableController, SolveController, StencilController, CANCEL, COPY_AS,
+    GENERATE, PASTE_PUZZLE, REDO, SOLVE, SOLVE_ALL, UNDO,
+};
+use crate::formatters::{NonZeroFormatter, SeedFormatter};
+use crate::state::{AppState, BoardLens, Choice, ParamsState};
+use crate::widgets::{GraphWidget, GridWidget};
+use crate::{nonzero_textbox, param, usize_textbox};
 use druid::text::format::ParseFormatter;
-use druid::widget::{Button, Checkbox, Either, Flex, Label, SizedBox, TextBox, ValueTextBox};
+use druid::widget::{
+    Button, Checkbox, CrossAxisAlignment, Either, Flex, Label, ProgressBar, RadioGroup, SizedBox,
+    TextBox, ValueTextBox,
+};
 use druid::{
-    BoxConstraints, Color, Data, Env, Event, EventCtx, LayoutCtx, LifeCycle, LifeCycleCtx,
-    PaintCtx, UpdateCtx, Widget, WidgetExt,
+    commands, BoxConstraints, Color, Data, Env, Event, EventCtx, FileDialogOptions, FileSpec,
+    LayoutCtx, Lens, LensExt, LifeCycle, LifeCycleCtx, LocalizedString, MenuDesc, MenuItem,
+    PaintCtx, SysMods, UpdateCtx, Widget, WidgetExt,
 };
+use lights_out::data::Topology;
+use lights_out::generator::Difficulty;
+use lights_out::neighbourhood::{Neighbourhood, Tiling};
+use lights_out::notation::Notation;
+use lights_out::settings::Optimize;
+use strum::IntoEnumIterator;
+
+/// Hides `widget` while a graph replaces the grid.
+fn grid_only(widget: impl Widget<ParamsState> + 'static) -> impl Widget<ParamsState> {
+    Either::new(
+        |data: &ParamsState, _env| data.params().graph.is_none(),
+        widget,
+        SizedBox::empty(),
+    )
+}
+
+/// Draws the grid selected by `lens`, or its graph if it has one.
+fn board(active: bool, lens: BoardLens) -> impl Widget<ParamsState> {
+    Either::new(
+        |data: &ParamsState, _env| data.params().graph.is_some(),
+        GraphWidget::new(active).lens(lens),
+        GridWidget::new(active).lens(lens),
+    )
+}
 
-fn build_params() -> impl Widget<SolverState> {
+/// Lets one of `variants` be chosen for the parameter lensed by `lens`.
+fn radio_group<T: Copy + PartialEq + 'static>(
+    variants: impl IntoIterator<Item = (&'static str, T)>,
+    lens: impl Lens<ParamsState, T> + 'static,
+) -> impl Widget<ParamsState> {
+    RadioGroup::new(
+        variants
+            .into_iter()
+            .map(|(name, variant)| (name, Choice(variant)))
+            .collect::<Vec<_>>(),
+    )
+    .lens(lens.map(
+        |value| Choice(*value),
+        |value, choice: Choice<T>| *value = choice.0,
+    ))
+}
+
+fn build_params() -> impl Widget<AppState> {
     let left = Flex::row()
         .with_child(
             Flex::column()
@@ -41,21 +90,194 @@ fn build_params() -> impl Widget<SolverState> {
                 .with_child(usize_textbox!(objective)),
         );
 
+    let topology = Flex::row()
+        .with_child(Label::new("Topology:").align_right())
+        .with_default_spacer()
+        .with_child(
+            radio_group(
+                [
+                    Topology::Bounded,
+                    Topology::Torus,
+                    Topology::HorizontalCylinder,
+                    Topology::VerticalCylinder,
+                ]
+                .map(|topology| (topology.name(), topology)),
+                param!(topology),
+            )
+            .controller(ParamsController {}),
+        )
+        .cross_axis_alignment(CrossAxisAlignment::Start);
+
+    let tiling = Flex::row()
+        .with_child(Label::new("Tiling:").align_right())
+        .with_default_spacer()
+        .with_child(
+            radio_group(
+                [Tiling::Square, Tiling::Hex, Tiling::Triangle]
+                    .map(|tiling| (tiling.name(), tiling)),
+                param!(tiling),
+            )
+            .controller(ParamsController {}),
+        )
+        .cross_axis_alignment(CrossAxisAlignment::Start);
+
+    let weights = Flex::row()
+        .with_child(
+            Flex::column()
+                .with_child(Label::new("Centre weight:").align_right())
+                .with_default_spacer()
+                .with_child(Label::new("Neighbour weight:").align_right()),
+        )
+        .with_default_spacer()
+        .with_child(
+            Flex::column()
+                .with_child(usize_textbox!(centre_weight).fix_width(50.0))
+                .with_default_spacer()
+                .with_child(usize_textbox!(neighbour_weight).fix_width(50.0)),
+        );
+
+    let neighbourhood = Flex::row()
+        .with_child(Label::new("Neighbourhood:").align_right())
+        .with_default_spacer()
+        .with_child(
+            Flex::column()
+                .with_child(
+                    radio_group(
+                        [
+                            Neighbourhood::Plus,
+                            Neighbourhood::XShape,
+                            Neighbourhood::Moore,
+                            Neighbourhood::Knight,
+                            Neighbourhood::Custom,
+                        ]
+                        .map(|neighbourhood| (neighbourhood.name(), neighbourhood)),
+                        param!(neighbourhood),
+                    )
+                    .controller(ParamsController {}),
+                )
+                .with_default_spacer()
+                .with_child(
+                    Flex::row()
+                        .with_child(Label::new("Radius:"))
+                        .with_default_spacer()
+                        .with_child(nonzero_textbox!(radius).fix_width(50.0)),
+                )
+                .cross_axis_alignment(CrossAxisAlignment::Start),
+        )
+        .with_default_spacer()
+        .with_child(Either::new(
+            |data: &ParamsState, _env| data.params().neighbourhood == Neighbourhood::Custom,
+            GridWidget::new(true)
+                .lens(BoardLens::Mask)
+                .controller(StencilController {})
+                .fix_size(90.0, 90.0),
+            SizedBox::empty(),
+        ))
+        .cross_axis_alignment(CrossAxisAlignment::Start);
+
+    let graph = Flex::column()
+        .with_child(Label::new("Graph file:"))
+        .with_default_spacer()
+        .with_child(
+            TextBox::new()
+                .lens(ParamsState::graph_path)
+                .fix_width(150.0),
+        )
+        .with_default_spacer()
+        .with_child(
+            Flex::row()
+                .with_child(
+                    Button::new("Load").on_click(|_ctx, data: &mut ParamsState, _env| {
+                        let path = data.graph_path.clone();
+                        data.solve_error = match data.params_mut().load_graph(&path) {
+                            Ok(()) => String::new(),
+                            Err(e) => format!("cannot load graph: {}", e),
+                        };
+                    }),
+                )
+                .with_default_spacer()
+                .with_child(Either::new(
+                    |data: &ParamsState, _env| data.params().graph.is_some(),
+                    Button::new("Close").on_click(|_ctx, data: &mut ParamsState, _env| {
+                        data.params_mut().close_graph();
+                    }),
+                    SizedBox::empty(),
+                )),
+        )
+        .cross_axis_alignment(CrossAxisAlignment::Start);
+
     Flex::row()
-        .with_child(left)
+        .with_child(grid_only(left))
         .with_default_spacer()
         .with_child(right)
+        .with_default_spacer()
+        .with_child(grid_only(topology))
+        .with_default_spacer()
+        .with_child(grid_only(tiling))
+        .with_default_spacer()
+        .with_child(weights)
+        .with_default_spacer()
+        .with_child(graph)
+        .with_default_spacer()
+        .with_child(grid_only(Either::new(
+            |data: &ParamsState, _env| data.params().tiling == Tiling::Square,
+            neighbourhood,
+            SizedBox::empty(),
+        )))
+        .cross_axis_alignment(CrossAxisAlignment::Start)
         .padding(10.0)
         .border(Color::grey(0.6), 2.0)
         .rounded(5.0)
-        .lens(SolverState::params)
+        .lens(AppState::params)
+}
+
+fn solve_info(data: &ParamsState) -> String {
+    if !data.solve_error.is_empty() {
+        format!("({})", data.solve_error)
+    } else if data.solve_time.is_empty() {
+        String::new()
+    } else if let Some(metric) = data.params().optimized.description() {
+        format!("({}, {})", data.solve_time, metric)
+    } else {
+        format!("({})", data.solve_time)
+    }
 }
 
-fn build_grids() -> Box<dyn Widget<SolverState>> {
+fn solvable_info(data: &ParamsState) -> String {
+    match data.solvable {
+        Some(true) => String::from("(solvable)"),
+        Some(false) => String::from("(not solvable)"),
+        None => String::new(),
+    }
+}
+
+fn build_grids() -> Box<dyn Widget<AppState>> {
     let puzzle = Flex::column()
-        .with_child(Label::new("Puzzle:"))
+        .with_child(
+            Flex::row()
+                .with_flex_child(
+                    Label::new(|data: &ParamsState, _env: &_| solvable_info(data))
+                        .with_text_color(Color::rgba(0., 0., 0., 0.))
+                        .align_right(),
+                    1.,
+                )
+                .with_child(Label::new("Puzzle:").center())
+                .with_flex_child(
+                    Label::new(|data: &ParamsState, _env: &_| solvable_info(data))
+                        .with_text_color(Color::grey(0.6))
+                        .align_left(),
+                    1.,
+                ),
+        )
+        .with_default_spacer()
+        .with_flex_child(board(true, BoardLens::Puzzle), 1.0)
+        .padding(10.0)
+        .expand_width();
+
+    let target = Flex::column()
+        .with_child(Label::new("Target:"))
         .with_default_spacer()
-        .with_flex_child(GridWidget::new(true).lens(Params::puzzle), 1.0)
+        .with_flex_child(board(true, BoardLens::Target), 1.0)
         .padding(10.0)
         .expand_width();
 
@@ -63,70 +285,143 @@ fn build_grids() -> Box<dyn Widget<SolverState>> {
         .with_child(
             Flex::row()
                 .with_flex_child(
-                    Label::new(|data: &Params, _env: &_| {
-                        if data.solve_time.is_empty() {
-                            String::new()
-                        } else {
-                            format!("({})", data.solve_time.clone())
-                        }
-                    })
-                    .with_text_color(Color::rgba(0., 0., 0., 0.))
-                    .align_right(),
+                    Label::new(|data: &ParamsState, _env: &_| solve_info(data))
+                        .with_text_color(Color::rgba(0., 0., 0., 0.))
+                        .align_right(),
                     1.,
                 )
                 .with_child(Label::new("Solution:").center())
                 .with_flex_child(
-                    Label::new(|data: &Params, _env: &_| {
-                        if data.solve_time.is_empty() {
-                            String::new()
-                        } else {
-                            format!("({})", data.solve_time.clone())
-                        }
-                    })
-                    .with_text_color(Color::grey(0.6))
-                    .align_left(),
+                    Label::new(|data: &ParamsState, _env: &_| solve_info(data))
+                        .with_text_color(Color::grey(0.6))
+                        .align_left(),
                     1.,
                 ),
         )
         .with_default_spacer()
-        .with_flex_child(GridWidget::new(false).lens(Params::solution), 1.0)
+        .with_flex_child(board(false, BoardLens::Solution), 1.0)
+        .with_child(Either::new(
+            |data: &ParamsState, _env| data.params().solutions.is_some(),
+            build_solution_stepper(),
+            SizedBox::empty(),
+        ))
         .padding(10.0)
         .expand_width();
 
     Flex::row()
         .with_flex_child(puzzle, 1.0)
         .with_default_spacer()
+        .with_flex_child(target, 1.0)
+        .with_default_spacer()
         .with_flex_child(solution, 1.0)
+        .controller(LayoutController {})
         .padding(10.0)
         .border(Color::grey(0.6), 2.0)
         .rounded(5.0)
-        .lens(SolverState::params)
+        .lens(AppState::params)
         .boxed()
 }
 
-fn build_top_row() -> impl Widget<SolverState> {
+fn build_solution_stepper() -> impl Widget<ParamsState> {
+    Flex::row()
+        .with_child(
+            Button::new("Previous").on_click(move |_ctx, data: &mut ParamsState, _env| {
+                data.step_solution(false);
+            }),
+        )
+        .with_default_spacer()
+        .with_child(
+            Label::new(
+                |data: &ParamsState, _env: &_| match &data.params().solutions {
+                    Some(solutions) => match solutions.count() {
+                        Some(count) => format!("Solution {} of {}", data.solution_index + 1, count),
+                        None => format!(
+                            "Solution {} ({} quiet patterns)",
+                            data.solution_index + 1,
+                            solutions.quiet_patterns.len()
+                        ),
+                    },
+                    None => String::new(),
+                },
+            )
+            .with_text_color(Color::grey(0.6)),
+        )
+        .with_default_spacer()
+        .with_child(
+            Button::new("Next").on_click(move |_ctx, data: &mut ParamsState, _env| {
+                data.step_solution(true);
+            }),
+        )
+        .padding((0.0, 10.0, 0.0, 0.0))
+}
+
+fn build_top_row() -> impl Widget<AppState> {
     Flex::row()
         .with_child(
             Checkbox::new("Play mode")
-                .lens(Params::play)
-                .controller(PlayController {})
-                .lens(SolverState::params),
+                .lens(ParamsState::play)
+                .lens(AppState::params),
         )
         .with_default_spacer()
         .with_child(
-            Button::new("Randomize").on_click(move |_ctx, data: &mut SolverState, _env| {
-                data.randomize();
+            Button::new("Randomize").on_click(move |_ctx, data: &mut AppState, _env| {
+                data.params.randomize();
             }),
         )
         .with_default_spacer()
+        .with_child(Label::new("Seed:"))
+        .with_child(
+            ValueTextBox::new(TextBox::new(), SeedFormatter)
+                .validate_while_editing(false)
+                .lens(param!(seed))
+                .lens(AppState::params)
+                .controller(SeedController)
+                .fix_width(100.0),
+        )
+        .with_default_spacer()
+        .with_child(
+            Button::new("Undo")
+                .on_click(|ctx, _data: &mut AppState, _env| ctx.submit_command(UNDO)),
+        )
+        .with_child(
+            Button::new("Redo")
+                .on_click(|ctx, _data: &mut AppState, _env| ctx.submit_command(REDO)),
+        )
+        .with_default_spacer()
+        .with_child(
+            Label::new(|data: &AppState, _env: &_| {
+                format!("States: {}", data.params.params().states)
+            })
+            .with_text_color(Color::grey(0.6)),
+        )
+        .with_default_spacer()
+        .with_child(
+            Label::new(|data: &AppState, _env: &_| {
+                format!("Objective: {}", data.params.params().objective)
+            })
+            .with_text_color(Color::grey(0.6)),
+        )
+        .with_default_spacer()
+        .with_child(
+            Label::new(|data: &AppState, _env: &_| {
+                format!("Topology: {}", data.params.params().topology.name())
+            })
+            .with_text_color(Color::grey(0.6)),
+        )
+        .with_default_spacer()
         .with_child(
-            Label::new(|data: &SolverState, _env: &_| format!("States: {}", data.params.states))
-                .with_text_color(Color::grey(0.6)),
+            Label::new(|data: &AppState, _env: &_| {
+                format!("Tiling: {}", data.params.params().tiling.name())
+            })
+            .with_text_color(Color::grey(0.6)),
         )
         .with_default_spacer()
         .with_child(
-            Label::new(|data: &SolverState, _env: &_| {
-                format!("Objective: {}", data.params.objective)
+            Label::new(|data: &AppState, _env: &_| {
+                format!(
+                    "Neighbourhood: {}",
+                    data.params.params().neighbourhood.name()
+                )
             })
             .with_text_color(Color::grey(0.6)),
         )
@@ -134,33 +429,146 @@ fn build_top_row() -> impl Widget<SolverState> {
         .padding((10.0, 4.0, 10.0, 10.0))
 }
 
-pub fn build_ui() -> impl Widget<SolverState> {
-    Flex::column()
-        .with_child(build_top_row())
-        .with_child(Either::new(
-            |data, _env| data.params.play,
-            SizedBox::empty(),
-            build_params(),
-        ))
+fn build_generate_row() -> impl Widget<AppState> {
+    let mut row = Flex::row().with_child(Label::new("Generate:"));
+    for difficulty in Difficulty::iter() {
+        row.add_default_spacer();
+        row.add_child(Button::new(difficulty.name()).on_click(
+            move |ctx, data: &mut AppState, _env| {
+                // Puzzles are rated like the solver rates solutions, by clicks unless told otherwise
+                let metric = match crate::SETTINGS.optimize {
+                    Optimize::None => Optimize::Clicks,
+                    metric => metric,
+                };
+                let goal = difficulty.goal(data.params.params(), metric, data.unique);
+                ctx.submit_command(GENERATE.with(goal));
+            },
+        ));
+    }
+    row.with_default_spacer()
+        .with_child(Checkbox::new("Unique solution").lens(AppState::unique))
+        .align_left()
+        .padding((10.0, 0.0, 10.0, 10.0))
+}
+
+fn build_solve_buttons() -> impl Widget<AppState> {
+    let solve = Flex::row()
+        .with_flex_child(
+            Button::new("Solve")
+                .fix_height(70.0)
+                .expand_width()
+                .on_click(move |ctx, _data: &mut AppState, _env| {
+                    ctx.submit_command(SOLVE);
+                }),
+            3.0,
+        )
         .with_default_spacer()
-        .with_flex_child(Rebuilder::new(), 1.0)
+        .with_flex_child(
+            Button::new("All solutions")
+                .fix_height(70.0)
+                .expand_width()
+                .on_click(move |ctx, _data: &mut AppState, _env| {
+                    ctx.submit_command(SOLVE_ALL);
+                }),
+            1.0,
+        );
+
+    let progress = Flex::row()
+        .with_flex_child(
+            ProgressBar::new().expand_width().lens(AppState::progress),
+            3.0,
+        )
         .with_default_spacer()
-        .with_child(
-            Button::new("Solve")
+        .with_flex_child(
+            Button::new("Cancel")
                 .fix_height(70.0)
                 .expand_width()
-                .on_click(move |_ctx, data: &mut SolverState, _env| {
-                    if data.solve().is_err() {
-                        data.params.solution.error = true;
-                    }
+                .on_click(move |ctx, _data: &mut AppState, _env| {
+                    ctx.submit_command(CANCEL);
                 }),
+            1.0,
+        );
+
+    Either::new(|data: &AppState, _env| data.busy, progress, solve)
+}
+
+pub fn build_ui() -> impl Widget<AppState> {
+    Flex::column()
+        .with_child(build_top_row().controller(BusyController))
+        .with_child(build_generate_row().controller(BusyController))
+        .with_child(
+            Either::new(
+                |data, _env| data.params.play,
+                SizedBox::empty(),
+                build_params(),
+            )
+            .controller(BusyController),
         )
+        .with_default_spacer()
+        .with_flex_child(Rebuilder::new().controller(BusyController), 1.0)
+        .with_default_spacer()
+        .with_child(build_solve_buttons())
         .padding(10.0)
+        .controller(SolveController::new())
+        .controller(FileController)
+        .controller(HistoryController)
+        .controller(SolvableController::new())
+}
+
+const PUZZLE_FILE: FileSpec = FileSpec::new("Lights Out puzzle", &["toml"]);
+
+pub fn build_menu() -> MenuDesc<AppState> {
+    let options = FileDialogOptions::new()
+        .allowed_types(vec![PUZZLE_FILE])
+        .default_type(PUZZLE_FILE);
+    let file = MenuDesc::new(LocalizedString::new("common-menu-file-menu"))
+        .append(
+            MenuItem::new(
+                LocalizedString::new("common-menu-file-open"),
+                commands::SHOW_OPEN_PANEL.with(options.clone()),
+            )
+            .hotkey(SysMods::Cmd, "o"),
+        )
+        .append(
+            MenuItem::new(
+                LocalizedString::new("common-menu-file-save-as"),
+                commands::SHOW_SAVE_PANEL.with(options.default_name("puzzle.toml")),
+            )
+            .hotkey(SysMods::Cmd, "s"),
+        );
+    let mut edit = MenuDesc::new(LocalizedString::new("common-menu-edit-menu"))
+        .append(
+            MenuItem::new(LocalizedString::new("common-menu-undo"), UNDO).hotkey(SysMods::Cmd, "z"),
+        )
+        .append(
+            MenuItem::new(LocalizedString::new("common-menu-redo"), REDO).hotkey(SysMods::Cmd, "y"),
+        )
+        .append_separator();
+    // Puzzles are exchanged through the clipboard in any notation, pasting guesses which one
+    for notation in Notation::iter() {
+        let mut item = MenuItem::new(
+            LocalizedString::new("lights-out-menu-copy-as")
+                .with_placeholder(format!("Copy as {}", notation.name())),
+            COPY_AS.with(notation),
+        );
+        if notation == Notation::Text {
+            item = item.hotkey(SysMods::CmdShift, "C");
+        }
+        edit = edit.append(item);
+    }
+    let edit = edit.append_separator().append(
+        MenuItem::new(
+            LocalizedString::new("lights-out-menu-paste-puzzle").with_placeholder("Paste puzzle"),
+            PASTE_PUZZLE,
+        )
+        .hotkey(SysMods::CmdShift, "V"),
+    );
+    MenuDesc::empty().append(file).append(edit)
 }
 
 /// builds a child Flex widget from some paramaters.
 struct Rebuilder {
-    inner: Box<dyn Widget<SolverState>>,
+    inner: Box<dyn Widget<AppState>>,
 }
 
 impl Rebuilder {
@@ -175,31 +583,19 @@ impl Rebuilder {
     }
 }
 
-impl Widget<SolverState> for Rebuilder {
-    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut SolverState, env: &Env) {
+impl Widget<AppState> for Rebuilder {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut AppState, env: &Env) {
         self.inner.event(ctx, event, data, env);
     }
 
-    fn lifecycle(
-        &mut self,
-        ctx: &mut LifeCycleCtx,
-        event: &LifeCycle,
-        data: &SolverState,
-        env: &Env,
-    ) {
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &AppState, env: &Env) {
         if let LifeCycle::WidgetAdded = event {
             self.rebuild_inner();
         }
         self.inner.lifecycle(ctx, event, data, env);
     }
 
-    fn update(
-        &mut self,
-        ctx: &mut UpdateCtx,
-        old_data: &SolverState,
-        data: &SolverState,
-        env: &Env,
-    ) {
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &AppState, data: &AppState, env: &Env) {
         if old_data.params.same(&data.params) {
             self.inner.update(ctx, old_data, data, env);
         } else {
@@ -212,13 +608,13 @@ impl Widget<SolverState> for Rebuilder {
         &mut self,
         ctx: &mut LayoutCtx,
         bc: &BoxConstraints,
-        data: &SolverState,
+        data: &AppState,
         env: &Env,
     ) -> druid::Size {
         self.inner.layout(ctx, bc, data, env)
     }
 
-    fn paint(&mut self, ctx: &mut PaintCtx, data: &SolverState, env: &Env) {
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &AppState, env: &Env) {
         self.inner.paint(ctx, data, env);
     }
 }
@@ -1,13 +1,19 @@
 use druid::{
-    kurbo::RoundedRect,
-    piet::{Text, TextAttribute, TextLayoutBuilder},
-    BoxConstraints, Color, Data, Env, Event, EventCtx, FontFamily, FontWeight, LayoutCtx, Lens,
-    LifeCycle, LifeCycleCtx, MouseButton, PaintCtx, Point, RenderContext, Size, UpdateCtx, Widget,
+    kurbo::{BezPath, Circle, Line, RoundedRect, Shape, Vec2},
+    piet::{Text, TextAttribute, TextLayout, TextLayoutBuilder},
+    BoxConstraints, Color, Env, Event, EventCtx, FontFamily, FontWeight, LayoutCtx, LifeCycle,
+    LifeCycleCtx, MouseButton, MouseEvent, PaintCtx, Point, RenderContext, Size, UpdateCtx, Widget,
 };
+use std::f64::consts::PI;
 
-use crate::data::{Grid, GridCoord};
+use crate::state::Board;
+use lights_out::data::{Grid, GridCoord};
+use lights_out::graph::Graph;
+use lights_out::neighbourhood::Tiling;
 
-#[derive(Clone, Data, Lens)]
+const SQRT_3: f64 = 1.732_050_807_568_877_2;
+
+#[derive(Clone)]
 pub struct GridWidget {
     cell_size: Size,
     active: bool,
@@ -23,47 +29,193 @@ impl GridWidget {
         }
     }
 
-    fn grid_coord(&self, p: Point, rows: usize, columns: usize) -> Option<GridCoord> {
+    fn grid_coord(&self, p: Point, data: &Grid) -> Option<GridCoord> {
         let w0 = self.cell_size.width;
         let h0 = self.cell_size.height;
         if p.x < 0.0 || p.y < 0.0 || w0 == 0.0 || h0 == 0.0 {
             return None;
         }
-        let row = (p.y / h0) as usize;
-        let col = (p.x / w0) as usize;
-        if row >= rows || col >= columns {
-            return None;
+        if data.tiling() == Tiling::Square {
+            let row = (p.y / h0) as usize;
+            let col = (p.x / w0) as usize;
+            if row >= data.rows() || col >= data.columns() {
+                return None;
+            }
+            Some(GridCoord { row, col })
+        } else {
+            (0..data.rows())
+                .flat_map(|row| (0..data.columns()).map(move |col| GridCoord { row, col }))
+                .find(|&coord| self.cell_path(data, coord, false).contains(p))
         }
-        Some(GridCoord { row, col })
+    }
+
+    /// Outline of the cell in `coord`, leaving a small gap from the neighbouring cells.
+    fn cell_path(&self, data: &Grid, coord: GridCoord, failed: bool) -> BezPath {
+        let w0 = self.cell_size.width;
+        let h0 = self.cell_size.height;
+        let row = coord.row as f64;
+        let col = coord.col as f64;
+
+        let vertices: Vec<Point> = match data.tiling() {
+            Tiling::Square => {
+                let rendered_cell_size = Size {
+                    width: w0 - 2.0,
+                    height: h0 - 2.0,
+                };
+                let point = Point {
+                    x: w0 * col + 1.0,
+                    y: h0 * row + 1.0,
+                };
+                let radius = if failed {
+                    f64::max(10.0, rendered_cell_size.min_side() / 5.0)
+                } else {
+                    rendered_cell_size.min_side() / 5.0
+                };
+                return RoundedRect::from_origin_size(point, rendered_cell_size, radius)
+                    .into_path(0.1);
+            }
+            Tiling::Hex => {
+                let shift = if coord.row % 2 == 1 { 0.5 } else { 0.0 };
+                let center = Point {
+                    x: w0 * (col + 0.5 + shift),
+                    y: h0 * (0.5 + 0.75 * row),
+                };
+                (0..6)
+                    .map(|k| center + Vec2::from_angle(PI / 6.0 + PI / 3.0 * k as f64) * h0 / 2.0)
+                    .collect()
+            }
+            Tiling::Triangle => {
+                let x = w0 * col / 2.0;
+                let y = h0 * row;
+                if (coord.row + coord.col).is_multiple_of(2) {
+                    vec![
+                        Point::new(x, y + h0),
+                        Point::new(x + w0 / 2.0, y),
+                        Point::new(x + w0, y + h0),
+                    ]
+                } else {
+                    vec![
+                        Point::new(x, y),
+                        Point::new(x + w0, y),
+                        Point::new(x + w0 / 2.0, y + h0),
+                    ]
+                }
+            }
+        };
+
+        let center = centroid(&vertices);
+        let mut path = BezPath::new();
+        for (i, vertex) in vertices.into_iter().enumerate() {
+            let vertex = center + (vertex - center) * 0.92;
+            if i == 0 {
+                path.move_to(vertex);
+            } else {
+                path.line_to(vertex);
+            }
+        }
+        path.close_path();
+        path
+    }
+}
+
+fn centroid(vertices: &[Point]) -> Point {
+    let sum = vertices
+        .iter()
+        .fold(Vec2::ZERO, |acc, vertex| acc + vertex.to_vec2());
+    (sum / vertices.len() as f64).to_point()
+}
+
+/// Size of the whole grid, taking the width of a cell as unit.
+fn extent(data: &Grid) -> Size {
+    let rows = data.rows() as f64;
+    let columns = data.columns() as f64;
+    match data.tiling() {
+        Tiling::Square => Size::new(columns, rows),
+        Tiling::Hex => {
+            let shift = if data.rows() > 1 { 0.5 } else { 0.0 };
+            Size::new(columns + shift, (0.75 * (rows - 1.0) + 1.0) * 2.0 / SQRT_3)
+        }
+        Tiling::Triangle => Size::new((columns + 1.0) / 2.0, rows * SQRT_3 / 2.0),
     }
 }
 
-impl Widget<Grid> for GridWidget {
-    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut Grid, _env: &Env) {
+/// Applies a mouse click on `coord` to `data`, according to the button and the modifiers pressed.
+/// In play mode a click changes every cell it affects, otherwise it edits the clicked cell.
+fn mouse_down(data: &mut Board, coord: Option<GridCoord>, e: &MouseEvent) {
+    let coord = match coord {
+        Some(coord) => coord,
+        None => return,
+    };
+    let grid = &mut data.grid;
+    let n = match e.button {
+        MouseButton::Left if e.mods.ctrl() => {
+            if !data.play {
+                grid.toggle_disabled(coord);
+            }
+            return;
+        }
+        MouseButton::Left | MouseButton::Right if e.mods.shift() => {
+            if !data.play {
+                grid.cycle_modulus(coord, e.button == MouseButton::Left);
+            }
+            return;
+        }
+        MouseButton::Left => 1,
+        MouseButton::Right => grid.modulus() - 1,
+        _ => return,
+    };
+    if data.play {
+        grid.click(coord, n);
+    } else {
+        grid.cycle_state(coord, n);
+    }
+}
+
+/// Colour of a cell in `state` out of `modulus` states and colour of the text drawn on it.
+fn cell_colors(active: bool, state: usize, modulus: usize) -> (Color, Color) {
+    let v = state as f64 / (modulus as f64 - 1.0);
+    let (r, g, b);
+    if active {
+        r = v;
+        g = v;
+        b = 0.0;
+    } else {
+        r = 0.0;
+        g = v * 0.5;
+        b = v;
+    };
+    let y = 0.299 * r + 0.587 * g + 0.114 * b;
+    (
+        Color::rgb(r, g, b),
+        if y > 0.5 { Color::BLACK } else { Color::WHITE },
+    )
+}
+
+impl Widget<Board> for GridWidget {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut Board, _env: &Env) {
         match event {
-            Event::MouseDown(e) if self.active => match e.button {
-                MouseButton::Left => {
-                    let coord = self.grid_coord(e.pos, data.rows, data.columns);
-                    data.click(coord, 1);
-                }
-                MouseButton::Right => {
-                    let coord = self.grid_coord(e.pos, data.rows, data.columns);
-                    data.click(coord, data.states - 1);
-                }
-                _ => {}
-            },
+            Event::MouseDown(e) if self.active => {
+                let coord = self.grid_coord(e.pos, &data.grid);
+                mouse_down(data, coord, e);
+            }
             Event::MouseMove(e) => {
-                self.hot_cell = self.grid_coord(e.pos, data.rows, data.columns);
+                self.hot_cell = self.grid_coord(e.pos, &data.grid);
                 ctx.request_paint();
             }
             _ => {}
         }
     }
 
-    fn lifecycle(&mut self, _ctx: &mut LifeCycleCtx, _event: &LifeCycle, _data: &Grid, _env: &Env) {
+    fn lifecycle(
+        &mut self,
+        _ctx: &mut LifeCycleCtx,
+        _event: &LifeCycle,
+        _data: &Board,
+        _env: &Env,
+    ) {
     }
 
-    fn update(&mut self, ctx: &mut UpdateCtx, _old_data: &Grid, _data: &Grid, _env: &Env) {
+    fn update(&mut self, ctx: &mut UpdateCtx, _old_data: &Board, _data: &Board, _env: &Env) {
         ctx.request_paint();
     }
 
@@ -71,12 +223,14 @@ impl Widget<Grid> for GridWidget {
         &mut self,
         _layout_ctx: &mut LayoutCtx,
         bc: &BoxConstraints,
-        data: &Grid,
+        data: &Board,
         _env: &Env,
     ) -> Size {
         let max_size = bc.max();
-        let mut width = data.columns as f64;
-        let mut height = data.rows as f64;
+        let Size {
+            mut width,
+            mut height,
+        } = extent(&data.grid);
 
         let ratio = max_size.width / width;
         width *= ratio;
@@ -90,103 +244,96 @@ impl Widget<Grid> for GridWidget {
         Size { width, height }
     }
 
-    fn paint(&mut self, ctx: &mut PaintCtx, data: &Grid, _env: &Env) {
+    fn paint(&mut self, ctx: &mut PaintCtx, board: &Board, _env: &Env) {
+        let data = &board.grid;
         let size: Size = ctx.size();
-        let w0 = size.width / data.columns as f64;
-        let h0 = size.height / data.rows as f64;
-
-        self.cell_size = Size {
-            width: w0,
-            height: h0,
-        };
-
-        let rendered_cell_size = Size {
-            width: w0 - 2.0,
-            height: h0 - 2.0,
+        self.cell_size = match data.tiling() {
+            Tiling::Square => Size {
+                width: size.width / data.columns() as f64,
+                height: size.height / data.rows() as f64,
+            },
+            Tiling::Hex => {
+                let w0 = size.width / extent(data).width;
+                Size::new(w0, w0 * 2.0 / SQRT_3)
+            }
+            Tiling::Triangle => {
+                let w0 = size.width / extent(data).width;
+                Size::new(w0, w0 * SQRT_3 / 2.0)
+            }
         };
+        let w0 = self.cell_size.width;
+        let h0 = self.cell_size.height;
 
-        if data.error {
-            for row in 0..data.rows {
-                for col in 0..data.columns {
-                    let coord = GridCoord { row, col };
-
-                    let point = Point {
-                        x: w0 * col as f64 + 1.0,
-                        y: h0 * row as f64 + 1.0,
-                    };
-
-                    let radius = f64::max(10.0, rendered_cell_size.min_side() / 5.0);
-                    let shape = RoundedRect::from_origin_size(point, rendered_cell_size, radius);
-
-                    ctx.fill(shape, &Color::RED);
+        // The solution grid shows clicks, which cycle through the states of every cell
+        let clicks_modulus = data.modulus();
+        for row in 0..data.rows() {
+            for col in 0..data.columns() {
+                let coord = GridCoord { row, col };
+                let shape = self.cell_path(data, coord, board.failed);
 
-                    if let Some(hot_cell) = self.hot_cell {
-                        if hot_cell == coord {
-                            if self.active {
-                                ctx.stroke(shape, &Color::WHITE, 2.0);
-                            } else {
-                                ctx.stroke(shape, &Color::GRAY, 1.0);
-                            }
-                        }
+                if data[coord].disabled {
+                    // Holes are not drawn, except for the outline of the hovered one while editing
+                    if self.active && !board.play && self.hot_cell == Some(coord) {
+                        ctx.stroke(&shape, &Color::grey(0.4), 1.0);
                     }
+                    continue;
                 }
-            }
-        } else {
-            for row in 0..data.rows {
-                for col in 0..data.columns {
-                    let coord = GridCoord { row, col };
 
+                if board.failed {
+                    ctx.fill(&shape, &Color::RED);
+                } else {
                     let cell_state = data[coord].state;
-                    let v = cell_state as f64 / (data.states as f64 - 1.0);
-                    let (r, g, b);
-                    if self.active {
-                        r = v;
-                        g = v;
-                        b = 0.0;
+                    let modulus = if self.active {
+                        data[coord].modulus
                     } else {
-                        r = 0.0;
-                        g = v * 0.5;
-                        b = v;
+                        clicks_modulus
                     };
-                    let cell_color = Color::rgb(r, g, b);
+                    let (cell_color, text_color) = cell_colors(self.active, cell_state, modulus);
 
-                    let point = Point {
-                        x: w0 * col as f64 + 1.0,
-                        y: h0 * row as f64 + 1.0,
-                    };
-
-                    let radius = rendered_cell_size.min_side() / 5.0;
-                    let shape = RoundedRect::from_origin_size(point, rendered_cell_size, radius);
+                    ctx.fill(&shape, &cell_color);
 
-                    ctx.fill(shape, &cell_color);
-
-                    if data.states > 2 {
+                    if modulus > 2 {
                         let label = cell_state.to_string();
-                        let font_size = self.cell_size.width / 3.0;
-                        let y = 0.299 * r + 0.587 * g + 0.114 * b;
+                        let font_size = match data.tiling() {
+                            Tiling::Triangle => self.cell_size.width / 4.0,
+                            _ => self.cell_size.width / 3.0,
+                        };
                         let text = ctx.text();
                         let layout = text
                             .new_text_layout(label)
                             .font(FontFamily::SANS_SERIF, font_size)
                             .default_attribute(TextAttribute::Weight(FontWeight::BOLD))
-                            .text_color(if y > 0.5 { Color::BLACK } else { Color::WHITE })
+                            .text_color(text_color)
                             .build()
                             .unwrap();
 
-                        let pos = Point {
-                            x: point.x + rendered_cell_size.width / 10.0,
-                            y: point.y + rendered_cell_size.height / 20.0,
+                        let pos = if data.tiling() == Tiling::Square {
+                            Point {
+                                x: w0 * col as f64 + 1.0 + (w0 - 2.0) / 10.0,
+                                y: h0 * row as f64 + 1.0 + (h0 - 2.0) / 20.0,
+                            }
+                        } else {
+                            let center = shape.bounding_box().center();
+                            let center = match data.tiling() {
+                                // The centroid of a triangle is closer to its base
+                                Tiling::Triangle if (row + col).is_multiple_of(2) => {
+                                    center + Vec2::new(0.0, h0 / 6.0)
+                                }
+                                Tiling::Triangle => center - Vec2::new(0.0, h0 / 6.0),
+                                _ => center,
+                            };
+                            center - layout.size().to_vec2() / 2.0
                         };
                         ctx.draw_text(&layout, pos);
                     }
+                }
 
-                    if let Some(hot_cell) = self.hot_cell {
-                        if hot_cell == coord {
-                            if self.active {
-                                ctx.stroke(shape, &Color::WHITE, 2.0);
-                            } else {
-                                ctx.stroke(shape, &Color::GRAY, 1.0);
-                            }
+                if let Some(hot_cell) = self.hot_cell {
+                    if hot_cell == coord {
+                        if self.active {
+                            ctx.stroke(&shape, &Color::WHITE, 2.0);
+                        } else {
+                            ctx.stroke(&shape, &Color::GRAY, 1.0);
                         }
                     }
                 }
@@ -194,3 +341,179 @@ impl Widget<Grid> for GridWidget {
         }
     }
 }
+
+/// Draws a graph puzzle as nodes linked by their edges.
+pub struct GraphWidget {
+    centers: Vec<Point>,
+    radius: f64,
+    active: bool,
+    hot_node: Option<GridCoord>,
+}
+
+impl GraphWidget {
+    pub fn new(active: bool) -> Self {
+        Self {
+            centers: vec![],
+            radius: 0.0,
+            active,
+            hot_node: None,
+        }
+    }
+
+    fn node_coord(&self, p: Point) -> Option<GridCoord> {
+        self.centers
+            .iter()
+            .position(|center| center.distance(p) <= self.radius)
+            .map(|col| GridCoord { row: 0, col })
+    }
+
+    /// Fits the positions of the nodes of `graph` in a square of side `side`.
+    fn place_nodes(&mut self, graph: &Graph, side: f64) {
+        let (min_x, max_x, min_y, max_y) = graph.positions.iter().fold(
+            (f64::MAX, f64::MIN, f64::MAX, f64::MIN),
+            |(min_x, max_x, min_y, max_y), &(x, y)| {
+                (min_x.min(x), max_x.max(x), min_y.min(y), max_y.max(y))
+            },
+        );
+        let span = f64::max(max_x - min_x, max_y - min_y);
+        let span = if span > 0.0 { span } else { 1.0 };
+
+        self.radius = f64::min(
+            side / (2.0 * (graph.nodes() as f64).sqrt() + 2.0),
+            side / 8.0,
+        );
+        let margin = self.radius + 2.0;
+        let scale = (side - 2.0 * margin) / span;
+        let offset = Vec2::new(
+            (span - (max_x - min_x)) / 2.0,
+            (span - (max_y - min_y)) / 2.0,
+        );
+        self.centers = graph
+            .positions
+            .iter()
+            .map(|&(x, y)| {
+                let p = Vec2::new(x - min_x, y - min_y) + offset;
+                Point::new(margin, margin) + p * scale
+            })
+            .collect();
+    }
+}
+
+impl Widget<Board> for GraphWidget {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut Board, _env: &Env) {
+        match event {
+            Event::MouseDown(e) if self.active => {
+                let coord = self.node_coord(e.pos);
+                mouse_down(data, coord, e);
+            }
+            Event::MouseMove(e) => {
+                self.hot_node = self.node_coord(e.pos);
+                ctx.request_paint();
+            }
+            _ => {}
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        _ctx: &mut LifeCycleCtx,
+        _event: &LifeCycle,
+        _data: &Board,
+        _env: &Env,
+    ) {
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, _old_data: &Board, _data: &Board, _env: &Env) {
+        ctx.request_paint();
+    }
+
+    fn layout(
+        &mut self,
+        _layout_ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        _data: &Board,
+        _env: &Env,
+    ) -> Size {
+        let side = bc.max().min_side();
+        Size::new(side, side)
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, board: &Board, _env: &Env) {
+        let data = &board.grid;
+        let graph = match data.graph() {
+            Some(graph) => graph.clone(),
+            None => return,
+        };
+        self.place_nodes(&graph, ctx.size().min_side());
+        let radius = self.radius;
+
+        let edge_color = Color::grey(0.5);
+        for &(from, to) in &graph.edges {
+            let disabled = |node| data[GridCoord { row: 0, col: node }].disabled;
+            if from == to || disabled(from) || disabled(to) {
+                continue;
+            }
+            let (start, end) = (self.centers[from], self.centers[to]);
+            let direction = (end - start).normalize();
+            let tip = end - direction * radius;
+            ctx.stroke(Line::new(start, tip), &edge_color, 2.0);
+            if graph.directed {
+                let back = tip - direction * (radius / 2.0);
+                let side = Vec2::new(-direction.y, direction.x) * (radius / 4.0);
+                let mut arrow = BezPath::new();
+                arrow.move_to(tip);
+                arrow.line_to(back + side);
+                arrow.line_to(back - side);
+                arrow.close_path();
+                ctx.fill(arrow, &edge_color);
+            }
+        }
+
+        // The solution grid shows clicks, which cycle through the states of every cell
+        let clicks_modulus = data.modulus();
+        for (col, &center) in self.centers.iter().enumerate() {
+            let coord = GridCoord { row: 0, col };
+            let shape = Circle::new(center, radius * 0.9);
+
+            if data[coord].disabled {
+                if self.active && !board.play && self.hot_node == Some(coord) {
+                    ctx.stroke(shape, &Color::grey(0.4), 1.0);
+                }
+                continue;
+            }
+
+            if board.failed {
+                ctx.fill(shape, &Color::RED);
+            } else {
+                let cell_state = data[coord].state;
+                let modulus = if self.active {
+                    data[coord].modulus
+                } else {
+                    clicks_modulus
+                };
+                let (cell_color, text_color) = cell_colors(self.active, cell_state, modulus);
+                ctx.fill(shape, &cell_color);
+
+                if modulus > 2 {
+                    let text = ctx.text();
+                    let layout = text
+                        .new_text_layout(cell_state.to_string())
+                        .font(FontFamily::SANS_SERIF, radius * 0.8)
+                        .default_attribute(TextAttribute::Weight(FontWeight::BOLD))
+                        .text_color(text_color)
+                        .build()
+                        .unwrap();
+                    ctx.draw_text(&layout, center - layout.size().to_vec2() / 2.0);
+                }
+            }
+
+            if self.hot_node == Some(coord) {
+                if self.active {
+                    ctx.stroke(shape, &Color::WHITE, 2.0);
+                } else {
+                    ctx.stroke(shape, &Color::GRAY, 1.0);
+                }
+            }
+        }
+    }
+}